@@ -0,0 +1,126 @@
+//! Compares `rutie_serde` with round-trips through JSON, which is the usual alternative for
+//! exchanging data between Rust and Ruby: `serde_json` on the Rust side and the `json` library on
+//! the Ruby side.
+//!
+//! Requires a Ruby the crate can link against: `cargo bench --bench json`.
+
+mod support;
+
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rutie::{AnyObject, Module, Object, RString, VM};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize)]
+struct Order {
+    id: u64,
+    customer: Customer,
+    items: Vec<Item>,
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Customer {
+    name: String,
+    email: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Item {
+    sku: String,
+    quantity: u32,
+    price: f64,
+    tags: Vec<String>,
+}
+
+// Nested hashes: a batch of orders with a few items each.
+fn orders() -> Vec<Order> {
+    (0..200)
+        .map(|id| Order {
+            id,
+            customer: Customer {
+                name: format!("Customer {}", id),
+                email: format!("customer{}@example.com", id),
+            },
+            items: (0..5)
+                .map(|i| Item {
+                    sku: format!("SKU-{}-{}", id, i),
+                    quantity: i + 1,
+                    price: 9.99 * f64::from(i + 1),
+                    tags: vec!["fresh".to_owned(), "local".to_owned()],
+                })
+                .collect(),
+            metadata: (0..3)
+                .map(|i| (format!("key{}", i), format!("value {}", i)))
+                .collect(),
+        })
+        .collect()
+}
+
+// A large array of integers.
+fn integers() -> Vec<i64> {
+    (0..100_000).collect()
+}
+
+// Long strings.
+fn strings() -> Vec<String> {
+    (0..10_000)
+        .map(|i| format!("{} {}", i, "lorem ipsum dolor sit amet ".repeat(8)))
+        .collect()
+}
+
+fn to_ruby_via_json<T>(value: &T) -> AnyObject
+where
+    T: serde::Serialize,
+{
+    let json = serde_json::to_string(value).unwrap();
+    Module::from_existing("JSON")
+        .protect_send("parse", &[RString::new_utf8(&json).to_any_object()])
+        .unwrap()
+}
+
+fn from_ruby_via_json<T>(object: &AnyObject) -> T
+where
+    T: DeserializeOwned,
+{
+    let json = object
+        .protect_send("to_json", &[])
+        .unwrap()
+        .try_convert_to::<RString>()
+        .unwrap();
+    serde_json::from_str(json.to_str()).unwrap()
+}
+
+fn compare<T>(c: &mut Criterion, name: &str, value: T)
+where
+    T: serde::Serialize + DeserializeOwned,
+{
+    let object = rutie_serde::new_ruby_object(&value).unwrap();
+    let mut group = c.benchmark_group(name);
+    group.bench_function("to ruby: rutie_serde", |b| {
+        b.iter(|| rutie_serde::new_ruby_object(black_box(&value)).unwrap())
+    });
+    group.bench_function("to ruby: serde_json + JSON.parse", |b| {
+        b.iter(|| to_ruby_via_json(black_box(&value)))
+    });
+    group.bench_function("from ruby: rutie_serde", |b| {
+        b.iter(|| rutie_serde::from_object::<T, _>(black_box(&object)).unwrap())
+    });
+    group.bench_function("from ruby: to_json + serde_json", |b| {
+        b.iter(|| from_ruby_via_json::<T>(black_box(&object)))
+    });
+    group.finish();
+}
+
+fn json(c: &mut Criterion) {
+    support::vm_init();
+    VM::require("json");
+    compare(c, "nested hashes", orders());
+    compare(c, "large array", integers());
+    compare(c, "strings", strings());
+}
+
+criterion_group!(benches, json);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Measures the per-struct and per-field overhead which the class and Symbol caches address.
+//!
+//! Requires a Ruby the crate can link against: `cargo bench --bench lookups`.
+
+mod support;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rutie::AnyObject;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize)]
+struct Point {
+    x: i64,
+    y: i64,
+    label: String,
+    visible: bool,
+}
+
+fn points() -> Vec<Point> {
+    (0..1_000)
+        .map(|i| Point {
+            x: i,
+            y: -i,
+            label: format!("point {}", i),
+            visible: i % 2 == 0,
+        })
+        .collect()
+}
+
+fn lookups(c: &mut Criterion) {
+    support::vm_init();
+    let points = points();
+    let object: AnyObject = rutie_serde::new_ruby_object(&points).unwrap();
+
+    c.bench_function("serialize 1000 structs", |b| {
+        b.iter(|| rutie_serde::new_ruby_object(black_box(&points)).unwrap())
+    });
+    c.bench_function("deserialize 1000 structs", |b| {
+        b.iter(|| rutie_serde::from_object::<Vec<Point>, _>(black_box(&object)).unwrap())
+    });
+}
+
+criterion_group!(benches, lookups);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+//! Shared setup for the benchmarks, which run inside an embedded Ruby VM.
+
+use std::sync::Once;
+
+use rutie::VM;
+
+static VM_INIT: Once = Once::new();
+
+/// Starts the embedded Ruby VM, once per process, with the standard library on the load path.
+pub fn vm_init() {
+    VM_INIT.call_once(|| {
+        VM::init();
+        VM::init_loadpath();
+    });
+}
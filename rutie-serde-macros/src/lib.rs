@@ -0,0 +1,352 @@
+//! Procedural macros for `rutie_serde`. Use them through the re-exports in `rutie_serde`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Expr, ExprLit, Fields, FnArg, GenericArgument,
+    Ident, ItemFn, Lit, Meta, Pat, PathArguments, ReturnType, Token, Type,
+};
+
+/// Turns an ordinary function into a Ruby method, as `rutie_serde_methods!` does.
+///
+/// ```ignore
+/// #[rutie_serde::method(exception = ruby_class!(MyGem::Error))]
+/// fn hello(name: String) -> Result<String, MyError> {
+///     Ok(format!("Hello {}", name))
+/// }
+///
+/// // in `Init_*`:
+/// class.def_self("hello", hello);
+/// ```
+///
+/// The function is replaced by an `extern "C"` method of the same name, which deserializes each
+/// argument through `MethodArgument`, calls the original function, serializes its result (or
+/// raises its error with `IntoException`, for any return type named `Result`, including aliases
+/// such as `io::Result<T>`), and re-raises panics as Ruby exceptions. The optional
+/// `exception` argument is the `rutie::Class` used for panics and `rutie_serde` errors. It
+/// defaults to the class for each kind of failure set up by `rutie_serde::ruby_errors::init`, or
+/// to `StandardError` if that wasn't called.
+///
+/// A first parameter named `itself` receives the method's receiver, and may be of any
+/// `rutie::Object` type. Otherwise the receiver is ignored. Calls with the wrong number of
+/// arguments raise an `ArgumentError`, unless `rutie_serde::set_lenient_arity` turned this off.
+#[proc_macro_attribute]
+pub fn method(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attribute as MethodOptions);
+    let function = parse_macro_input!(item as ItemFn);
+    expand_method(options, function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implements `FromRubyObject` and `MethodArgument` for a struct wrapping a single
+/// `rutie::Object`, such as `AnyObject` or `rutie::Hash`.
+///
+/// ```ignore
+/// #[derive(FromRubyObject)]
+/// #[ruby_class = "Rack::Request"]
+/// pub struct Request(AnyObject);
+/// ```
+///
+/// Method arguments which aren't a kind of the `ruby_class` raise a `TypeError`. Without the
+/// attribute, any object is accepted.
+#[proc_macro_derive(FromRubyObject, attributes(ruby_class))]
+pub fn derive_from_ruby_object(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    expand_from_ruby_object(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_from_ruby_object(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "`FromRubyObject` can't be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                "`FromRubyObject` can only be derived for structs",
+            ))
+        }
+    };
+    let field = match fields.iter().collect::<Vec<_>>().as_slice() {
+        [field] => *field,
+        _ => {
+            return Err(Error::new(
+                fields.span(),
+                "`FromRubyObject` structs must have exactly one field, holding the object",
+            ))
+        }
+    };
+    let value = quote_spanned! {field.ty.span()=>
+        ::std::convert::From::from(::rutie::Object::value(&object))
+    };
+    let construct = match (fields, &field.ident) {
+        (Fields::Named(_), Some(field_name)) => quote!(#name { #field_name: #value }),
+        _ => quote!(#name(#value)),
+    };
+
+    let mut ruby_class = quote!(::std::option::Option::None);
+    for attribute in &input.attrs {
+        if !attribute.path().is_ident("ruby_class") {
+            continue;
+        }
+        let class_path =
+            match &attribute.meta {
+                Meta::NameValue(meta) => match &meta.value {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(class_path),
+                        ..
+                    }) => class_path,
+                    value => return Err(Error::new(
+                        value.span(),
+                        "expected a class path string, as in `#[ruby_class = \"Rack::Request\"]`",
+                    )),
+                },
+                meta => {
+                    return Err(Error::new(
+                        meta.span(),
+                        "expected `#[ruby_class = \"ClassPath\"]`",
+                    ))
+                }
+            };
+        ruby_class = quote!(::std::option::Option::Some(#class_path));
+    }
+
+    Ok(quote! {
+        impl ::rutie_serde::FromRubyObject for #name {
+            fn ruby_class() -> ::std::option::Option<&'static str> {
+                #ruby_class
+            }
+
+            fn from_ruby_object_unchecked(object: ::rutie::AnyObject) -> Self {
+                #construct
+            }
+        }
+
+        impl<'a> ::rutie_serde::MethodArgument<'a> for #name {
+            fn from_method_arguments(
+                arguments: &'a [::rutie::AnyObject],
+                position: &mut usize,
+            ) -> ::std::option::Option<::rutie_serde::Result<Self>> {
+                ::rutie_serde::__from_ruby_object_argument(arguments, position)
+            }
+        }
+    })
+}
+
+struct MethodOptions {
+    exception: Option<Expr>,
+}
+
+impl Parse for MethodOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut options = MethodOptions { exception: None };
+        let pairs = Punctuated::<MethodOption, Token![,]>::parse_terminated(input)?;
+        for MethodOption { name, value } in pairs {
+            match name.to_string().as_str() {
+                "exception" if options.exception.is_none() => options.exception = Some(value),
+                "exception" => return Err(Error::new(name.span(), "duplicate `exception` option")),
+                _ => {
+                    return Err(Error::new(
+                        name.span(),
+                        "unknown option, expected `exception`",
+                    ))
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+struct MethodOption {
+    name: Ident,
+    value: Expr,
+}
+
+impl Parse for MethodOption {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(MethodOption {
+            name,
+            value: input.parse()?,
+        })
+    }
+}
+
+fn expand_method(options: MethodOptions, function: ItemFn) -> syn::Result<TokenStream2> {
+    let signature = &function.sig;
+    if let Some(receiver) = signature.receiver() {
+        return Err(Error::new(
+            receiver.span(),
+            "methods can't take `self`, use an `itself` parameter for the receiver",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new(
+            signature.generics.span(),
+            "methods can't be generic",
+        ));
+    }
+
+    let name = &signature.ident;
+    let visibility = &function.vis;
+    // Without an explicit class, each kind of failure gets its class from `ruby_errors`.
+    let exception_class = |kind: &str| match &options.exception {
+        Some(exception) => quote!(#exception),
+        None => {
+            let kind = Ident::new(kind, Span::call_site());
+            quote!(::rutie_serde::ruby_errors::default_class(
+                ::rutie_serde::ruby_errors::ErrorClass::#kind
+            ))
+        }
+    };
+    let deserialization_class = exception_class("Deserialization");
+    let serialization_class = exception_class("Serialization");
+    let error_class = exception_class("Error");
+    let panic_class = exception_class("Panic");
+
+    let mut receiver_type = quote!(::rutie::AnyObject);
+    let mut call_arguments = Vec::new();
+    let mut arity = Vec::new();
+    let mut deserialize_arguments = Vec::new();
+    for (index, input) in signature.inputs.iter().enumerate() {
+        let argument = match input {
+            FnArg::Typed(argument) => argument,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new(receiver.span(), "unexpected `self`"))
+            }
+        };
+        let ident = match &*argument.pat {
+            Pat::Ident(pat) => pat.ident.clone(),
+            pat => return Err(Error::new(pat.span(), "expected an argument name")),
+        };
+        let ty = &argument.ty;
+        if index == 0 && ident == "itself" {
+            receiver_type = quote!(#ty);
+            call_arguments.push(quote!(itself));
+            continue;
+        }
+        let variable = format_ident!("__argument_{}", index);
+        let description = format!(
+            "Argument '{}: {}' not found for method '{}'",
+            ident,
+            quote!(#ty),
+            name
+        );
+        let context = format!("When deserializing arg: {}", ident);
+        // Spanned to the argument's type, so a type which can't be deserialized is reported there.
+        let from_arguments = quote_spanned! {ty.span()=>
+            <#ty as ::rutie_serde::MethodArgument>::from_method_arguments(&arguments, &mut position)
+        };
+        arity.push(quote_spanned! {ty.span()=>
+            arity = arity.with_argument(
+                <#ty as ::rutie_serde::MethodArgument>::positional_arguments(),
+                false,
+            );
+        });
+        deserialize_arguments.push(quote! {
+            let #variable: #ty = #from_arguments
+                .unwrap_or_else(|| ::std::result::Result::Err(::rutie_serde::Error::from(#description)))
+                .chain_context(|| #context)
+                .map_err(|error| IntoException::into_exception(error, #deserialization_class))?;
+        });
+        call_arguments.push(quote!(#variable));
+    }
+
+    let convert_result = if returns_result(&signature.output) {
+        quote! {
+            let value = result.map_err(|error| IntoException::into_exception(error, #error_class))?;
+        }
+    } else {
+        quote! {
+            let value = result;
+        }
+    };
+
+    let mut inner = function.clone();
+    inner.vis = syn::Visibility::Inherited;
+    let itself = Ident::new("itself", Span::call_site());
+
+    Ok(quote! {
+        #[allow(non_snake_case)]
+        #visibility extern "C" fn #name(
+            argc: ::rutie::types::Argc,
+            argv: *const ::rutie::AnyObject,
+            #itself: #receiver_type,
+        ) -> ::rutie::AnyObject {
+            #[allow(unused_imports)]
+            use ::rutie_serde::{IntoAnyObject, IntoException, MethodArgument, ResultExt};
+
+            #inner
+
+            // Heap allocations stay inside the closure: raising an exception longjmps out of
+            // this function without running destructors.
+            let result = ::rutie_serde::panics::catch_and_raise(
+                #panic_class,
+                move || -> ::std::result::Result<::rutie::AnyObject, ::rutie::AnyException> {
+                    let arguments = ::rutie::util::parse_arguments(argc, argv);
+                    #[allow(unused_mut)]
+                    let mut arity = ::rutie_serde::Arity::default();
+                    #(#arity)*
+                    arity
+                        .check(arguments.len())
+                        .map_err(|error| IntoException::into_exception(error, #deserialization_class))?;
+                    #[allow(unused_mut, unused_variables)]
+                    let mut position = 0;
+                    #(#deserialize_arguments)*
+                    let result = #name(#(#call_arguments),*);
+                    #convert_result
+                    IntoAnyObject::into_any_object(value)
+                        .map_err(|error| IntoException::into_exception(error, #serialization_class))
+                },
+            );
+
+            match result {
+                ::std::result::Result::Ok(value) => value,
+                ::std::result::Result::Err(exception) => {
+                    ::rutie::VM::raise_ex(exception);
+                    unreachable!("::rutie::VM::raise_ex")
+                }
+            }
+        }
+    })
+}
+
+// Whether the function returns a `Result`, whose error is raised rather than serialized. Aliases
+// such as `rutie_serde::Result<T>`, `io::Result<T>` or `anyhow::Result<T>` count too, as the
+// last path segment is all a macro can see.
+fn returns_result(output: &ReturnType) -> bool {
+    let ty = match output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => return false,
+    };
+    let path = match &**ty {
+        Type::Path(path) => &path.path,
+        _ => return false,
+    };
+    let segment = match path.segments.last() {
+        Some(segment) => segment,
+        None => return false,
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if segment.ident == "Result" => arguments
+            .args
+            .iter()
+            .any(|argument| matches!(argument, GenericArgument::Type(_))),
+        _ => false,
+    }
+}
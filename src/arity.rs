@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rutie::{AnyException, Class, Object, RString};
+
+use crate::{Error, Result};
+
+static LENIENT_ARITY: AtomicBool = AtomicBool::new(false);
+
+/// Turns arity checking of `rutie_serde` methods off (or back on) for the whole process.
+///
+/// By default calling a method with the wrong number of arguments raises an `ArgumentError`,
+/// like a method defined in Ruby. In lenient mode extra arguments are ignored, and missing ones
+/// fail only when they have no default value.
+pub fn set_lenient_arity(lenient: bool) {
+    LENIENT_ARITY.store(lenient, Ordering::Relaxed);
+}
+
+/// The number of positional arguments a `rutie_serde` method accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Arity {
+    pub required: usize,
+    pub optional: usize,
+    /// Whether any number of further arguments is accepted, as with a `Splat`.
+    pub rest: bool,
+}
+
+impl Arity {
+    /// Adds an argument taking up `positional` positional arguments, or any number for `None`.
+    pub fn with_argument(mut self, positional: Option<usize>, has_default: bool) -> Self {
+        match positional {
+            None => self.rest = true,
+            Some(count) if has_default => self.optional += count,
+            Some(count) => self.required += count,
+        }
+        self
+    }
+
+    /// Checks that `given` arguments are acceptable, failing with an `ArgumentError` otherwise.
+    pub fn check(&self, given: usize) -> Result<()> {
+        if LENIENT_ARITY.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let max = self.required + self.optional;
+        if given >= self.required && (self.rest || given <= max) {
+            return Ok(());
+        }
+        let expected = if self.rest {
+            format!("{}+", self.required)
+        } else if self.optional == 0 {
+            self.required.to_string()
+        } else {
+            format!("{}..{}", self.required, max)
+        };
+        let message = format!(
+            "wrong number of arguments (given {}, expected {})",
+            given, expected
+        );
+        let exception = Class::from_existing("ArgumentError")
+            .new_instance(&[RString::new_utf8(&message).to_any_object()]);
+        Err(Error::from(AnyException::from(exception.value())))
+    }
+}
@@ -0,0 +1,77 @@
+//! `#[serde(with = "rutie_serde::bigdecimal_serde")]` for `rust_decimal::Decimal` fields,
+//! exchanged with Ruby as `BigDecimal` objects. Requires the `rust_decimal` feature.
+//!
+//! Values are converted through their exact decimal string representation, so no precision is
+//! lost to floating point. Deserialization also accepts Integers and numeric Strings, and Floats
+//! via their shortest representation. Only works with `rutie_serde`'s own `Serializer` and
+//! `Deserializer`.
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! struct LineItem {
+//!     #[serde(with = "rutie_serde::bigdecimal_serde")]
+//!     price: Decimal,
+//! }
+//! ```
+
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use rutie::{AnyObject, Module, Object, RString};
+use serde::{de, ser, Deserializer, Serializer};
+
+use crate::{raw, Error, Result, ResultExt};
+
+pub fn serialize<S>(value: &Decimal, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let decimal = to_big_decimal(value)
+        .chain_context(|| format!("When converting {} to a BigDecimal", value))
+        .map_err(ser::Error::custom)?;
+    raw::serialize(&decimal, serializer)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let object = raw::deserialize(deserializer)?;
+    from_big_decimal(&object)
+        .chain_context(|| "When deserializing a BigDecimal")
+        .map_err(de::Error::custom)
+}
+
+fn to_big_decimal(value: &Decimal) -> Result<AnyObject> {
+    let kernel = Module::from_existing("Kernel");
+    kernel.protect_send(
+        "require",
+        &[RString::new_utf8("bigdecimal").to_any_object()],
+    )?;
+    Ok(kernel.protect_send(
+        "BigDecimal",
+        &[RString::new_utf8(&value.to_string()).to_any_object()],
+    )?)
+}
+
+fn from_big_decimal(object: &AnyObject) -> Result<Decimal> {
+    let class_name = object
+        .protect_send("class", &[])?
+        .protect_send("name", &[])?
+        .try_convert_to::<RString>()
+        .map(|name| name.to_string());
+    let is_big_decimal = class_name.as_deref() == Ok("BigDecimal");
+    // `BigDecimal#to_s` uses scientific notation unless asked for the plain ("F") format.
+    let arguments = if is_big_decimal {
+        vec![RString::new_utf8("F").to_any_object()]
+    } else {
+        vec![]
+    };
+    let string = object
+        .protect_send("to_s", &arguments)?
+        .try_convert_to::<RString>()?
+        .to_string();
+    Decimal::from_str(&string)
+        .or_else(|_| Decimal::from_scientific(&string))
+        .map_err(|e| Error::from(format!("{:?} is not a valid decimal: {}", string, e)))
+}
@@ -0,0 +1,69 @@
+use rutie::{AnyObject, Object, Proc, VM};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{from_object, new_ruby_object, MethodArgument, Result, ResultExt};
+
+/// The block passed to a `rutie_serde_methods!` method.
+///
+/// Declare it as the method's last argument to require a block; it doesn't take up one of the
+/// method's positional arguments. Methods with an optional block can call `RubyBlock::current`
+/// instead.
+///
+/// ```ignore
+/// rutie_serde_methods!(
+///     Catalog,
+///     _itself,
+///     ruby_class!(Exception),
+///     fn each_product(filter: Filter, block: RubyBlock) -> Result<(), rutie_serde::Error> {
+///         for product in products(&filter) {
+///             block.call::<_, ()>(&product)?;
+///         }
+///         Ok(())
+///     }
+/// );
+/// ```
+pub struct RubyBlock(Proc);
+
+impl RubyBlock {
+    /// Returns the block given to the current method, if any.
+    pub fn current() -> Option<RubyBlock> {
+        if VM::is_block_given() {
+            Some(RubyBlock(VM::block_proc()))
+        } else {
+            None
+        }
+    }
+
+    /// Calls the block with `argument` serialized into a Ruby object, and deserializes what the
+    /// block returns. As the block is a `Proc`, a serialized tuple or `Vec` is spread over the
+    /// block's parameters, as with `yield`.
+    pub fn call<T, R>(&self, argument: T) -> Result<R>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let argument =
+            new_ruby_object(argument).chain_context(|| "When serializing block argument")?;
+        let result = self.0.protect_send("call", &[argument])?;
+        from_object(&result).chain_context(|| "When deserializing the block's result")
+    }
+
+    /// The block as a Ruby `Proc`.
+    pub fn to_proc(&self) -> &Proc {
+        &self.0
+    }
+}
+
+impl<'a> MethodArgument<'a> for RubyBlock {
+    fn from_method_arguments(
+        _arguments: &'a [AnyObject],
+        _position: &mut usize,
+    ) -> Option<Result<Self>> {
+        Some(RubyBlock::current().ok_or_else(|| "No block given".into()))
+    }
+
+    fn positional_arguments() -> Option<usize> {
+        Some(0)
+    }
+}
@@ -0,0 +1,114 @@
+//! Per-thread caches of Ruby objects which `rutie_serde` looks up for every struct or field.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use rutie::rubysys::symbol::rb_intern;
+use rutie::rubysys::vm::rb_funcallv;
+use rutie::types::{Argc, Id, Value};
+use rutie::{AnyException, AnyObject, Class, Module, Object, RString, Symbol, VM};
+
+use crate::pinned::pin_forever;
+
+thread_local! {
+    // Keyed by the address and length of the name, which is cheaper to hash than its contents
+    // and stable for `'static` names.
+    static CORE_CLASSES: RefCell<HashMap<(usize, usize), Value>> = RefCell::new(HashMap::new());
+    static SYMBOLS: RefCell<HashMap<(usize, usize), Value>> = RefCell::new(HashMap::new());
+    static FROZEN_STRINGS: RefCell<HashMap<(usize, usize), Value>> = RefCell::new(HashMap::new());
+    static METHOD_IDS: RefCell<HashMap<(usize, usize), Id>> = RefCell::new(HashMap::new());
+    static REQUIRED: RefCell<HashMap<(usize, usize), bool>> = RefCell::new(HashMap::new());
+}
+
+fn key(name: &'static str) -> (usize, usize) {
+    (name.as_ptr() as usize, name.len())
+}
+
+/// The core class `name`, such as `Hash` or `Object`.
+///
+/// Only use this for classes which Ruby never removes, as the cache keeps no reference to them
+/// for the GC.
+pub(crate) fn core_class(name: &'static str) -> Class {
+    let value = CORE_CLASSES.with(|classes| {
+        *classes
+            .borrow_mut()
+            .entry(key(name))
+            .or_insert_with(|| Class::from_existing(name).value())
+    });
+    Class::from(value)
+}
+
+/// The Symbol `name`. Symbols created from Rust strings are never garbage collected, so they can
+/// be cached as they are.
+pub(crate) fn symbol(name: &'static str) -> Symbol {
+    let value = SYMBOLS.with(|symbols| {
+        *symbols
+            .borrow_mut()
+            .entry(key(name))
+            .or_insert_with(|| Symbol::new(name).value())
+    });
+    Symbol::from(value)
+}
+
+/// A frozen String of `name`, pinned so that it can be cached. Frozen Strings are stored as Hash
+/// keys as they are, whereas other Strings are copied for every entry.
+pub(crate) fn frozen_string(name: &'static str) -> RString {
+    let value = FROZEN_STRINGS.with(|strings| {
+        *strings.borrow_mut().entry(key(name)).or_insert_with(|| {
+            let string = RString::new_utf8(name).freeze();
+            pin_forever(&string);
+            string.value()
+        })
+    });
+    RString::from(value)
+}
+
+/// Whether the library `feature` could be loaded, calling `require` only the first time.
+pub(crate) fn require(feature: &'static str) -> bool {
+    REQUIRED.with(|required| {
+        *required
+            .borrow_mut()
+            .entry(key(feature))
+            .or_insert_with(|| {
+                Module::from_existing("Kernel")
+                    .protect_send("require", &[RString::new_utf8(feature).to_any_object()])
+                    .is_ok()
+            })
+    })
+}
+
+/// The ID of the method `name`. IDs are never garbage collected either.
+fn method_id(name: &'static str) -> Id {
+    METHOD_IDS.with(|ids| {
+        *ids.borrow_mut().entry(key(name)).or_insert_with(|| {
+            let name = CString::new(name).expect("method names have no NUL bytes");
+            unsafe { rb_intern(name.as_ptr()) }
+        })
+    })
+}
+
+/// Like `Object::protect_send`, but calls the method by its cached ID rather than interning
+/// `name` on every call, for methods called once per element or entry.
+pub(crate) fn send<O>(
+    receiver: &O,
+    name: &'static str,
+    arguments: &[AnyObject],
+) -> Result<AnyObject, AnyException>
+where
+    O: Object,
+{
+    let receiver = receiver.value();
+    let id = method_id(name);
+    let call = || {
+        // An `AnyObject` is laid out as its `VALUE`, as `rutie_serde_methods!` relies on for
+        // `argv` too.
+        let argv = arguments.as_ptr() as *const Value;
+        AnyObject::from(unsafe { rb_funcallv(receiver, id, arguments.len() as Argc, argv) })
+    };
+    VM::protect(call).map_err(|_| {
+        let exception = VM::error_info().expect("an exception after a failed rb_protect");
+        VM::clear_error_info();
+        exception
+    })
+}
@@ -0,0 +1,97 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use rutie::{AnyObject, Class, Object, RString};
+
+use crate::ractor::shareable_lambda;
+use crate::{Error, Result, ResultExt};
+
+static CLASS_MAP: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+
+fn class_map() -> &'static RwLock<HashMap<String, String>> {
+    CLASS_MAP.get_or_init(Default::default)
+}
+
+/// A process-wide registry of Ruby classes to serialize Rust structs into.
+///
+/// When the `Serializer` serializes a registered struct, it calls `new` on the mapped Ruby class
+/// with the struct's fields as keyword arguments, instead of producing a Hash:
+///
+/// ```ignore
+/// ClassMap::register::<User>("MyApp::User");
+/// // Serializing `User { id: 1, name: "Jo".into() }` now returns
+/// // `MyApp::User.new(id: 1, name: "Jo")`.
+/// ```
+///
+/// The mapping takes precedence over `SerializerConfig::struct_target`. Classes are looked up
+/// by constant path when used, so they may be defined after registration.
+pub struct ClassMap;
+
+impl ClassMap {
+    /// Maps the Rust struct `T` to the Ruby class at `class_path`.
+    ///
+    /// Structs are identified by the name serde sees, which is the type's name unless the
+    /// container has `#[serde(rename)]`; use `register_name` for such types.
+    pub fn register<T>(class_path: &str) {
+        let type_name = ::std::any::type_name::<T>();
+        // Strip the module path and any generic parameters: `my_crate::User<T>` -> `User`.
+        let name = type_name.split('<').next().unwrap_or(type_name);
+        let name = name.rsplit("::").next().unwrap_or(name);
+        Self::register_name(name, class_path);
+    }
+
+    /// Maps Rust structs serialized under the (serde) name `struct_name` to the Ruby class at
+    /// `class_path`.
+    pub fn register_name(struct_name: &str, class_path: &str) {
+        class_map()
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(struct_name.to_owned(), class_path.to_owned());
+    }
+
+    /// Removes the mapping for `struct_name`, if any.
+    pub fn unregister_name(struct_name: &str) {
+        class_map()
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(struct_name);
+    }
+
+    pub(crate) fn lookup(struct_name: &str) -> Option<String> {
+        class_map()
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(struct_name)
+            .cloned()
+    }
+}
+
+thread_local! {
+    // `->(klass, fields) { klass.new(**fields) }`, as calling a method with keyword arguments
+    // isn't possible through `protect_send`.
+    static NEW_WITH_KEYWORDS: OnceCell<AnyObject> = const { OnceCell::new() };
+}
+
+/// Instantiates the Ruby class at `class_path` with the entries of `fields` as keyword arguments.
+pub(crate) fn instantiate(class_path: &str, fields: &AnyObject) -> Result<AnyObject> {
+    let class = Class::from_existing("Object")
+        .protect_send(
+            "const_get",
+            &[RString::new_utf8(class_path).to_any_object()],
+        )
+        .map_err(Error::from)
+        .chain_context(|| format!("When looking up class {}", class_path))?;
+    let constructor = NEW_WITH_KEYWORDS.with(|constructor| -> Result<AnyObject> {
+        if let Some(constructor) = constructor.get() {
+            return Ok(constructor.clone());
+        }
+        let lambda =
+            shareable_lambda("->(klass, fields) { klass.new(**fields.transform_keys(&:to_sym)) }")?;
+        Ok(constructor.get_or_init(|| lambda).clone())
+    })?;
+    constructor
+        .protect_send("call", &[class, fields.clone()])
+        .map_err(Error::from)
+        .chain_context(|| format!("When instantiating {}", class_path))
+}
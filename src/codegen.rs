@@ -0,0 +1,360 @@
+//! Build-script helpers which generate the extension's `Init_*` entry point.
+//!
+//! Instead of registering every method by hand, mark each `rutie_serde_methods!` invocation with
+//! the Ruby class it belongs to:
+//!
+//! ```ignore
+//! // rutie_serde: class RubyRustDemo
+//! rutie_serde_methods!(
+//!     RubyRustDemo,
+//!     _itself,
+//!     ruby_class!(Exception),
+//!     fn hello(name: String) -> String {
+//!         format!("Hello {}", name)
+//!     }
+//! );
+//! ```
+//!
+//! then generate the bindings from `build.rs`:
+//!
+//! ```ignore
+//! fn main() {
+//!     let out_dir = std::env::var("OUT_DIR").unwrap();
+//!     rutie_serde::codegen::generate_bindings(
+//!         "ruby_rust_demo",
+//!         "src",
+//!         std::path::Path::new(&out_dir).join("rutie_serde_bindings.rs"),
+//!     )
+//!     .unwrap();
+//! }
+//! ```
+//!
+//! and include them once from the crate root:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/rutie_serde_bindings.rs"));
+//! ```
+//!
+//! The generated `Init_ruby_rust_demo` defines (or reopens) every marked class, nesting classes
+//! written as `Outer::Inner` in modules defined as needed, and registers each method of the invocation, after installing
+//! `panics::install_hook` and marking the extension Ractor-safe with
+//! `ractor::mark_extension_safe`, so the method bodies must be safe to call from any Ractor. Two
+//! markers are understood:
+//!
+//!  - `// rutie_serde: class Name` registers the methods as class methods (`def_self`).
+//!  - `// rutie_serde: instance_methods Name` registers them as instance methods (`def`).
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{Result, ResultExt};
+
+const MARKER: &str = "// rutie_serde:";
+const METHODS_MACRO: &str = "rutie_serde_methods!";
+
+/// How the methods of one annotated invocation are registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodKind {
+    /// Methods on the class object itself, defined with `def_self`.
+    Class,
+    /// Instance methods, defined with `def`.
+    Instance,
+}
+
+/// One annotated `rutie_serde_methods!` invocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassBinding {
+    /// The Ruby class name, possibly nested (`Outer::Inner`).
+    pub ruby_class: String,
+    pub kind: MethodKind,
+    /// The Rust module containing the methods, e.g. `crate::users`.
+    pub module_path: String,
+    pub methods: Vec<String>,
+}
+
+/// Scans `src_dir` and writes the generated bindings for `Init_<extension_name>` to `out_file`.
+///
+/// Also prints the `cargo:rerun-if-changed` lines for every scanned file, so it's meant to be
+/// called from a build script.
+pub fn generate_bindings<S, O>(extension_name: &str, src_dir: S, out_file: O) -> Result<()>
+where
+    S: AsRef<Path>,
+    O: AsRef<Path>,
+{
+    let src_dir = src_dir.as_ref();
+    let files = source_files(src_dir)?;
+    for file in &files {
+        println!("cargo:rerun-if-changed={}", file.display());
+    }
+    let bindings = scan_files(src_dir, &files)?;
+    let out_file = out_file.as_ref();
+    fs::write(out_file, render_bindings(extension_name, &bindings))
+        .map_err(|e| format!("{}", e).into())
+        .chain_context(|| format!("When writing bindings to {}", out_file.display()))
+}
+
+/// Returns every annotated invocation found in the `.rs` files below `src_dir`.
+pub fn scan_source_dir<S>(src_dir: S) -> Result<Vec<ClassBinding>>
+where
+    S: AsRef<Path>,
+{
+    let src_dir = src_dir.as_ref();
+    let files = source_files(src_dir)?;
+    scan_files(src_dir, &files)
+}
+
+fn scan_files(src_dir: &Path, files: &[PathBuf]) -> Result<Vec<ClassBinding>> {
+    let mut bindings = vec![];
+    for file in files {
+        let source = fs::read_to_string(file)
+            .map_err(|e| format!("{}", e).into())
+            .chain_context(|| format!("When reading {}", file.display()))?;
+        let module_path = module_path(src_dir, file);
+        bindings.extend(
+            scan_source(&source, &module_path)
+                .chain_context(|| format!("When scanning {}", file.display()))?,
+        );
+    }
+    Ok(bindings)
+}
+
+fn source_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("{}", e).into())
+        .chain_context(|| format!("When listing {}", dir.display()))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("{}", e))?.path();
+        if path.is_dir() {
+            files.extend(source_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Maps `src/lib.rs` to `crate`, `src/users.rs` and `src/users/mod.rs` to `crate::users`.
+fn module_path(src_dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(src_dir).unwrap_or(file);
+    let mut path = vec!["crate".to_owned()];
+    for component in relative.with_extension("").iter() {
+        path.push(component.to_string_lossy().into_owned());
+    }
+    let is_root = path.len() == 2 && (path[1] == "lib" || path[1] == "main");
+    if is_root || path.last().is_some_and(|last| last == "mod") {
+        path.pop();
+    }
+    path.join("::")
+}
+
+/// Returns the annotated invocations in `source`, with methods resolved relative to
+/// `module_path`.
+pub fn scan_source(source: &str, module_path: &str) -> Result<Vec<ClassBinding>> {
+    let mut bindings = vec![];
+    let mut offset = 0;
+    while let Some(found) = source[offset..].find(MARKER) {
+        let marker_start = offset + found;
+        let line_end = source[marker_start..]
+            .find('\n')
+            .map_or(source.len(), |end| marker_start + end);
+        let (kind, ruby_class) = parse_marker(&source[marker_start + MARKER.len()..line_end])?;
+        let invocation = source[line_end..]
+            .find(METHODS_MACRO)
+            .map(|start| line_end + start + METHODS_MACRO.len())
+            .ok_or_else(|| {
+                format!(
+                    "No {} invocation follows the marker for {}",
+                    METHODS_MACRO, ruby_class
+                )
+            })?;
+        let (methods, end) = top_level_methods(source, invocation)
+            .chain_context(|| format!("When reading the methods of {}", ruby_class))?;
+        bindings.push(ClassBinding {
+            ruby_class,
+            kind,
+            module_path: module_path.to_owned(),
+            methods,
+        });
+        offset = end;
+    }
+    Ok(bindings)
+}
+
+fn parse_marker(marker: &str) -> Result<(MethodKind, String)> {
+    let mut words = marker.split_whitespace();
+    let kind = match words.next() {
+        Some("class") => MethodKind::Class,
+        Some("instance_methods") => MethodKind::Instance,
+        other => {
+            return Err(format!(
+                "Unknown rutie_serde marker {:?}, expected `class` or `instance_methods`",
+                other.unwrap_or("")
+            )
+            .into())
+        }
+    };
+    let name = words
+        .next()
+        .filter(|name| {
+            name.split("::").all(|part| {
+                part.chars().next().is_some_and(char::is_uppercase)
+                    && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+            })
+        })
+        .ok_or_else(|| format!("Invalid Ruby class name in marker {:?}", marker.trim()))?;
+    Ok((kind, name.to_owned()))
+}
+
+/// Collects the names of the `fn` items directly inside the macro invocation whose delimiter
+/// starts at (or after) `start`. Returns them with the offset just past the invocation.
+fn top_level_methods(source: &str, start: usize) -> Result<(Vec<String>, usize)> {
+    let bytes = source.as_bytes();
+    let mut methods = vec![];
+    let mut depth = 0usize;
+    let mut i = start;
+    let mut expect_name = false;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = source[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            b'"' => {
+                i = skip_string(bytes, i + 1, 0);
+                continue;
+            }
+            b'r' if is_raw_string(bytes, i) => {
+                let hashes = bytes[i + 1..].iter().take_while(|&&b| b == b'#').count();
+                i = skip_string(bytes, i + 2 + hashes, hashes);
+                continue;
+            }
+            b'\'' => {
+                i = skip_char_or_lifetime(bytes, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                if depth == 0 {
+                    return Err("Unbalanced delimiters".into());
+                }
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((methods, i + 1));
+                }
+            }
+            c if c == b'_' || c.is_ascii_alphabetic() => {
+                let end = i + bytes[i..]
+                    .iter()
+                    .take_while(|b| **b == b'_' || b.is_ascii_alphanumeric())
+                    .count();
+                let word = &source[i..end];
+                if depth == 1 {
+                    if expect_name {
+                        methods.push(word.to_owned());
+                        expect_name = false;
+                    } else if word == "fn" {
+                        expect_name = true;
+                    }
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Err("Unterminated macro invocation".into())
+}
+
+fn is_raw_string(bytes: &[u8], i: usize) -> bool {
+    let previous_is_ident = i > 0 && (bytes[i - 1] == b'_' || bytes[i - 1].is_ascii_alphanumeric());
+    let hashes = bytes[i + 1..].iter().take_while(|&&b| b == b'#').count();
+    !previous_is_ident && bytes.get(i + 1 + hashes) == Some(&b'"')
+}
+
+// Returns the offset just past the closing quote of a string whose contents start at `i`.
+fn skip_string(bytes: &[u8], mut i: usize, hashes: usize) -> usize {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if hashes == 0 => i += 2,
+            b'"' if bytes[i + 1..]
+                .iter()
+                .take(hashes)
+                .filter(|&&b| b == b'#')
+                .count()
+                == hashes =>
+            {
+                return i + 1 + hashes
+            }
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn skip_char_or_lifetime(bytes: &[u8], i: usize) -> usize {
+    if bytes.get(i + 1) == Some(&b'\\') {
+        // An escaped char literal such as '\n' or '\u{1F600}'.
+        return bytes[i + 2..]
+            .iter()
+            .position(|&b| b == b'\'')
+            .map_or(bytes.len(), |end| i + 2 + end + 1);
+    }
+    // A char literal is a single (possibly multi-byte) character between quotes; anything else is
+    // a lifetime.
+    let char_len = bytes.get(i + 1).map_or(1, |&b| {
+        if b < 0x80 {
+            1
+        } else {
+            (b.leading_ones() as usize).max(1)
+        }
+    });
+    if bytes.get(i + 1 + char_len) == Some(&b'\'') {
+        i + 2 + char_len
+    } else {
+        i + 1
+    }
+}
+
+/// Renders the `Init_<extension_name>` function registering `bindings`.
+pub fn render_bindings(extension_name: &str, bindings: &[ClassBinding]) -> String {
+    let mut out = String::new();
+    out.push_str("// @generated by rutie_serde::codegen. Do not edit.\n\n");
+    out.push_str("#[allow(non_snake_case)]\n#[no_mangle]\n");
+    let _ = writeln!(out, "pub extern \"C\" fn Init_{}() {{", extension_name);
+    out.push_str("    ::rutie_serde::panics::install_hook();\n");
+    out.push_str("    ::rutie_serde::ractor::mark_extension_safe();\n");
+    for binding in bindings {
+        out.push_str("    {\n");
+        // Reopening a class keeps its superclass, whatever it is.
+        let _ = writeln!(
+            out,
+            "        #[allow(unused_mut)]\n        let mut class = ::rutie_serde::__class_or_define_or_raise({:?});",
+            binding.ruby_class
+        );
+        let define = match binding.kind {
+            MethodKind::Class => "def_self",
+            MethodKind::Instance => "def",
+        };
+        for method in &binding.methods {
+            let _ = writeln!(
+                out,
+                "        ::rutie::Object::{}(&mut class, {:?}, {}::{});",
+                define, method, binding.module_path, method
+            );
+        }
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    out
+}
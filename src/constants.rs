@@ -0,0 +1,325 @@
+use std::fmt;
+
+use rutie::types::Argc;
+use rutie::{AnyObject, Boolean, Class, Object, Symbol, VM};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::Serialize;
+
+use crate::cache;
+use crate::panics::catch_and_raise;
+use crate::{from_object, new_ruby_object, Error, IntoException, Result, ResultExt};
+
+/// Sets the constant `name` on `target` (a `rutie::Module` or `rutie::Class`) to the Ruby
+/// representation of `value`, as produced by `new_ruby_object`.
+pub fn define_constant<O, T>(target: &O, name: &str, value: &T) -> Result<AnyObject>
+where
+    O: Object,
+    T: ?Sized + Serialize,
+{
+    let object = new_ruby_object(value)?;
+    target
+        .protect_send(
+            "const_set",
+            &[Symbol::new(name).to_any_object(), object.clone()],
+        )
+        .map_err(Error::from)
+        .chain_context(|| format!("When defining constant {}", name))?;
+    Ok(object)
+}
+
+/// Defines one constant on `target` per variant of the fieldless enum `T`.
+///
+/// Variant names are taken from `T`'s `Deserialize` implementation (so `#[serde(rename)]` and
+/// `#[serde(rename_all)]` are respected) and converted to SCREAMING_SNAKE_CASE for the constant
+/// name. Each constant holds the `Serialize` representation of its variant, e.g. with
+/// `#[serde(rename_all = "snake_case")]` `Status::InProgress` becomes `IN_PROGRESS = "in_progress"`.
+pub fn define_enum_constants<O, T>(target: &O) -> Result<Vec<T>>
+where
+    O: Object,
+    T: Serialize + DeserializeOwned,
+{
+    enum_variants::<T>()?
+        .iter()
+        .map(|variant| {
+            let value = unit_variant::<T>(variant)?;
+            define_constant(target, &constant_name(variant), &value)?;
+            Ok(value)
+        })
+        .collect()
+}
+
+/// Turns `module` into a first-class Ruby API for the fieldless enum `T`.
+///
+/// On top of the per-variant constants of `define_enum_constants`, this defines:
+///
+///  - `VALUES`, an Array of every variant's Ruby representation.
+///  - `valid?(value)`, which returns whether `value` deserializes into `T`.
+///  - `coerce(value)`, which deserializes `value` into `T` and returns its canonical Ruby
+///    representation, raising `ArgumentError` if `value` isn't one of the allowed values.
+///
+/// Because both methods go through the deserializer, `:active`, `"active"` and `Status::ACTIVE`
+/// are all accepted for a variant serialized as `"active"`.
+pub fn define_enum_module<O, T>(module: &mut O) -> Result<()>
+where
+    O: Object,
+    T: Serialize + DeserializeOwned,
+{
+    let values = define_enum_constants::<O, T>(module)?;
+    define_constant(module, "VALUES", &values)?;
+    module.define_singleton_method("valid?", enum_module_valid::<T>);
+    module.define_singleton_method("coerce", enum_module_coerce::<T>);
+    Ok(())
+}
+
+fn single_argument(argc: Argc, argv: *const AnyObject) -> Result<AnyObject> {
+    let mut arguments = rutie::util::parse_arguments(argc, argv);
+    if arguments.len() == 1 {
+        Ok(arguments.remove(0))
+    } else {
+        Err(format!(
+            "wrong number of arguments (given {}, expected 1)",
+            arguments.len()
+        )
+        .into())
+    }
+}
+
+fn raise_on_error(result: Result<AnyObject>) -> AnyObject {
+    match result {
+        Ok(value) => value,
+        Err(error) => {
+            let exception = error.into_exception(Class::from_existing("ArgumentError"));
+            VM::raise_ex(exception);
+            unreachable!("VM::raise_ex")
+        }
+    }
+}
+
+extern "C" fn enum_module_valid<T>(
+    argc: Argc,
+    argv: *const AnyObject,
+    _itself: AnyObject,
+) -> AnyObject
+where
+    T: DeserializeOwned,
+{
+    let result = catch_and_raise(Class::from_existing("ArgumentError"), move || {
+        let value = single_argument(argc, argv)?;
+        let valid = from_object::<T, _>(&value).is_ok();
+        Ok(Boolean::new(valid).to_any_object())
+    });
+    raise_on_error(result)
+}
+
+extern "C" fn enum_module_coerce<T>(
+    argc: Argc,
+    argv: *const AnyObject,
+    _itself: AnyObject,
+) -> AnyObject
+where
+    T: Serialize + DeserializeOwned,
+{
+    let result = catch_and_raise(Class::from_existing("ArgumentError"), move || {
+        let value = single_argument(argc, argv)?;
+        let variant: T = from_object(&value)?;
+        new_ruby_object(variant)
+    });
+    raise_on_error(result)
+}
+
+/// Returns the class at the constant path `path`, such as `"MyGem::Models::User"`, defining it
+/// if it doesn't exist yet, along with any missing modules it is nested in.
+///
+/// New classes are subclasses of `superclass`, or of `Object` for `None`. An existing class must
+/// be a subclass of `superclass`, so an extension loaded before its Ruby definitions agrees with
+/// them later on.
+pub fn class_or_define(path: &str, superclass: Option<&Class>) -> Result<Class> {
+    let mut segments: Vec<&str> = path.split("::").collect();
+    let name = segments.pop().unwrap_or(path);
+    let mut namespace = cache::core_class("Object").to_any_object();
+    for segment in segments {
+        namespace = match const_get(&namespace, segment)? {
+            Some(module) => module,
+            None => {
+                let module = cache::core_class("Module").protect_send("new", &[])?;
+                const_set(&namespace, segment, module)?
+            }
+        };
+    }
+    let superclass = superclass
+        .map(Object::to_any_object)
+        .unwrap_or_else(|| cache::core_class("Object").to_any_object());
+    let class = match const_get(&namespace, name)? {
+        Some(class) => class,
+        None => {
+            let class =
+                cache::core_class("Class").protect_send("new", &[superclass.to_any_object()])?;
+            const_set(&namespace, name, class)?
+        }
+    };
+    let is_subclass = class
+        .protect_send("<=", std::slice::from_ref(&superclass))
+        .map(|is_subclass| is_subclass.value().is_true())
+        .unwrap_or(false);
+    if !is_subclass {
+        let superclass_name = superclass
+            .protect_send("name", &[])
+            .ok()
+            .and_then(|name| name.try_convert_to::<rutie::RString>().ok())
+            .map_or_else(|| "its superclass".to_owned(), |name| name.to_string());
+        return Err(format!(
+            "{} is already defined, but not as a {}",
+            path, superclass_name
+        )
+        .into());
+    }
+    Ok(class.try_convert_to::<Class>()?)
+}
+
+// `ruby_class_or_define!`, which panics like `ruby_class!` does for missing classes.
+#[doc(hidden)]
+pub fn __ruby_class_or_define(path: &str, superclass: Option<&Class>) -> Class {
+    class_or_define(path, superclass).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// `rutie_serde_class!` and the `Init_*` functions generated by `codegen`, which raise a
+// `TypeError` like Ruby's `class` keyword does when the constant isn't a class.
+#[doc(hidden)]
+pub fn __class_or_define_or_raise(path: &str) -> Class {
+    match class_or_define(path, None) {
+        Ok(class) => class,
+        Err(error) => {
+            let exception = error.into_exception(Class::from_existing("TypeError"));
+            VM::raise_ex(exception);
+            unreachable!("VM::raise_ex")
+        }
+    }
+}
+
+// The constant `name` of `namespace`, not looking at its ancestors.
+fn const_get(namespace: &AnyObject, name: &str) -> Result<Option<AnyObject>> {
+    let arguments = [
+        Symbol::new(name).to_any_object(),
+        Boolean::new(false).to_any_object(),
+    ];
+    if !namespace
+        .protect_send("const_defined?", &arguments)
+        .map_err(Error::from)
+        .chain_context(|| format!("When looking up {}", name))?
+        .value()
+        .is_true()
+    {
+        return Ok(None);
+    }
+    Ok(Some(namespace.protect_send("const_get", &arguments)?))
+}
+
+fn const_set(namespace: &AnyObject, name: &str, value: AnyObject) -> Result<AnyObject> {
+    Ok(namespace.protect_send("const_set", &[Symbol::new(name).to_any_object(), value])?)
+}
+
+/// Returns the variant names of the enum `T`, as they appear in its `Deserialize` implementation.
+pub fn enum_variants<T>() -> Result<&'static [&'static str]>
+where
+    T: DeserializeOwned,
+{
+    match T::deserialize(VariantsProbe) {
+        Err(ProbeError::Variants(variants)) => Ok(variants),
+        Err(ProbeError::Message(msg)) => Err(msg.into()),
+        Ok(_) => Err("enum_variants probe unexpectedly produced a value".into()),
+    }
+}
+
+pub(crate) fn unit_variant<T>(variant: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let deserializer: de::value::StrDeserializer<de::value::Error> = variant.into_deserializer();
+    T::deserialize(deserializer)
+        .map_err(|e| format!("{}", e).into())
+        .chain_context(|| format!("When building enum variant {}", variant))
+}
+
+/// Converts a variant name such as `InProgress`, `in_progress` or `in-progress` into a Ruby
+/// constant name (`IN_PROGRESS`).
+pub(crate) fn constant_name(variant: &str) -> String {
+    let mut name = String::with_capacity(variant.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in variant.chars() {
+        if !c.is_alphanumeric() {
+            if !name.is_empty() && !name.ends_with('_') {
+                name.push('_');
+            }
+        } else {
+            if c.is_uppercase() {
+                if let Some(p) = previous {
+                    if (p.is_lowercase() || p.is_numeric()) && !name.ends_with('_') {
+                        name.push('_');
+                    }
+                }
+            }
+            name.extend(c.to_uppercase());
+        }
+        previous = Some(c);
+    }
+    name
+}
+
+/// A `Deserializer` which never produces a value: it records the variant list handed to
+/// `deserialize_enum` and returns it as an error.
+struct VariantsProbe;
+
+#[derive(Debug)]
+enum ProbeError {
+    Variants(&'static [&'static str]),
+    Message(String),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProbeError::Variants(variants) => write!(f, "variants: {:?}", variants),
+            ProbeError::Message(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: fmt::Display,
+    {
+        ProbeError::Message(format!("{}", msg))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for VariantsProbe {
+    type Error = ProbeError;
+
+    fn deserialize_any<V>(self, _visitor: V) -> ::std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        Err(ProbeError::Message("expected a fieldless enum".to_owned()))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> ::std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        Err(ProbeError::Variants(variants))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
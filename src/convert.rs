@@ -0,0 +1,84 @@
+use std::ops::{Deref, DerefMut};
+
+use rutie::AnyObject;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{raw, Result};
+
+/// An escape hatch for types whose Ruby representation can't be expressed through serde's data
+/// model. Implementors convert to and from the raw `AnyObject` themselves.
+///
+/// serde can't look for this trait on its own, so conversions are opted into per field with
+/// `#[serde(with = "rutie_serde::ruby_convert_serde")]`, or by wrapping the type in `Converted`
+/// (which also works for `rutie_serde_methods!` arguments and return values).
+pub trait RubyConvert: Sized {
+    fn to_ruby(&self) -> Result<AnyObject>;
+
+    fn from_ruby(object: &AnyObject) -> Result<Self>;
+}
+
+/// A wrapper which serializes and deserializes `T` through its `RubyConvert` implementation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Converted<T>(pub T);
+
+impl<T> Deref for Converted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Converted<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> Serialize for Converted<T>
+where
+    T: RubyConvert,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ruby_convert_serde::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Converted<T>
+where
+    T: RubyConvert,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        ruby_convert_serde::deserialize(deserializer).map(Converted)
+    }
+}
+
+/// `#[serde(with = "rutie_serde::ruby_convert_serde")]` support for `RubyConvert` fields. Only
+/// works with `rutie_serde`'s own `Serializer` and `Deserializer`.
+pub mod ruby_convert_serde {
+    use super::*;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        T: RubyConvert,
+        S: Serializer,
+    {
+        let object = value.to_ruby().map_err(ser::Error::custom)?;
+        raw::serialize(&object, serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> ::std::result::Result<T, D::Error>
+    where
+        T: RubyConvert,
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize(deserializer)?;
+        T::from_ruby(&object).map_err(de::Error::custom)
+    }
+}
@@ -1,22 +1,325 @@
+use std::cell::{OnceCell, RefCell};
+use std::marker::PhantomData;
+use std::rc::Rc;
 use std::str;
 
 use log::debug;
-use rutie::{AnyObject, Array, Boolean, Class, Fixnum, Float, NilClass, Object, RString};
-use serde::de::{self, Deserialize, DeserializeSeed, MapAccess, Visitor};
-
-use crate::{Error, ErrorKind, Result, ResultExt};
+use rutie::types::ValueType;
+use rutie::{
+    AnyObject, Array, Boolean, Class, EncodingSupport, Fixnum, Float, Hash, Integer, NilClass,
+    Object, RString, Symbol, VerifiedObject, VM,
+};
+use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, MapAccess, Visitor};
+
+use crate::cache;
+use crate::constants::constant_name;
+use crate::pinned::Pinned;
+use crate::ractor::shareable_lambda;
+use crate::raw;
+use crate::{Error, Path, PathSegment, RenameAll, Result, ResultExt};
 
 pub fn from_object<'a, T, O>(object: &O) -> Result<T>
 where
     T: Deserialize<'a>,
     O: Object,
 {
-    let deserializer = Deserializer::new(object);
+    from_object_with_config(object, DeserializerConfig::default())
+}
+
+/// Like `from_object`, but with non-default `DeserializerConfig` options.
+pub fn from_object_with_config<'a, T, O>(object: &O, config: DeserializerConfig) -> Result<T>
+where
+    T: Deserialize<'a>,
+    O: Object,
+{
+    let deserializer = Deserializer::with_config(object, config);
     let t = T::deserialize(deserializer)?;
     Ok(t)
 }
 
-fn object_class_name(object: &AnyObject) -> Result<String> {
+/// Like `from_object_with_config`, but also taking a single option in place of a whole
+/// `DeserializerConfig`, e.g. `from_object_with_options(&object, KeyNormalization::SnakeCase)`.
+pub fn from_object_with_options<'a, T, O, C>(object: &O, options: C) -> Result<T>
+where
+    T: Deserialize<'a>,
+    O: Object,
+    C: Into<DeserializerConfig>,
+{
+    from_object_with_config(object, options.into())
+}
+
+/// Like `from_object`, but returns the `Path` to the value which failed to deserialize alongside
+/// the error, for callers which want to inspect it programmatically.
+///
+/// ```ignore
+/// match from_object_with_path::<Order, _>(&object) {
+///     Ok(order) => ...,
+///     Err((_, path)) => println!("invalid value at {}", path), // e.g. `items[2].price`
+/// }
+/// ```
+pub fn from_object_with_path<T, O>(object: &O) -> ::std::result::Result<T, (Error, Path)>
+where
+    T: DeserializeOwned,
+    O: Object,
+{
+    from_object(object).map_err(|error| {
+        let path = error.path();
+        (error, path)
+    })
+}
+
+/// Like `from_object`, but borrows the contents of Ruby Strings instead of copying them, so `T`
+/// may have `&str` and `#[serde(borrow)] Cow<str>` fields.
+///
+/// Only frozen Strings in the UTF-8 or US-ASCII encoding held by `object` or its Hashes and
+/// Arrays are borrowed; this includes all String Hash keys, which Ruby freezes. Other Strings,
+/// such as those returned by reader methods, are copied, which `Cow` fields accept and `&str`
+/// fields report as an error.
+///
+/// The borrowed data lives in the Ruby heap, and is only valid as long as the Strings are
+/// referenced from `object` and not moved by GC compaction. Use the result within the current
+/// method call and don't keep it around while Ruby code runs.
+pub fn from_object_borrowed<'de, T, O>(object: &'de O) -> Result<T>
+where
+    T: Deserialize<'de>,
+    O: Object,
+{
+    let mut deserializer = Deserializer::new(object);
+    deserializer.borrow_strings = true;
+    T::deserialize(deserializer)
+}
+
+/// Deserializes `object` into an existing value, reusing its allocations where possible.
+///
+/// `String`s keep their buffer and `Vec`s keep their capacity (and deserialize their existing
+/// elements in place), which saves a lot of allocation when similar payloads are decoded
+/// repeatedly into the same value. Derived structs only deserialize their fields in place when
+/// `serde_derive`'s `deserialize_in_place` feature is enabled; otherwise they are replaced.
+pub fn from_object_in_place<'a, T, O>(place: &mut T, object: &O) -> Result<()>
+where
+    T: Deserialize<'a>,
+    O: Object,
+{
+    T::deserialize_in_place(Deserializer::new(object), place)
+}
+
+/// Lazily deserializes the elements of the Ruby Array `object`, one per call to `next`.
+///
+/// Unlike `from_object::<Vec<T>>`, no collection is built on the Rust side, so huge arrays can be
+/// processed, filtered or abandoned part way through without doubling memory. Other collections
+/// are read as for sequences (see `DeserializerConfig::strict_sequences`). If `object` isn't a
+/// sequence, the first item is the error. Element errors carry their index in `Error::path`, and
+/// don't end the iteration.
+///
+/// Elements are read from `object` as the iterator advances, so keep it referenced until the
+/// iterator is dropped.
+pub fn iter_from_object<T, O>(object: &O) -> FromObjectIter<T>
+where
+    T: DeserializeOwned,
+    O: Object,
+{
+    let seq = SeqAccess::new(Deserializer::new(object), &"a sequence").map_err(Some);
+    // A collection materialized into an Array (or converted by `to_ary`) is referenced by nothing
+    // but the iterator, which outlives the current call.
+    let array = seq.as_ref().ok().and_then(|seq| seq.array.as_ref());
+    FromObjectIter {
+        _array: array.map(Pinned::new),
+        seq,
+        marker: PhantomData,
+    }
+}
+
+/// The iterator returned by `iter_from_object`.
+pub struct FromObjectIter<T> {
+    // The error creating the `SeqAccess`, until it's been returned.
+    seq: ::std::result::Result<SeqAccess, Option<Error>>,
+    // Keeps the Array the elements are read from alive until the iterator is dropped.
+    _array: Option<Pinned>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Iterator for FromObjectIter<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        match &mut self.seq {
+            Ok(seq) => de::SeqAccess::next_element(seq).transpose(),
+            Err(error) => error.take().map(Err),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.seq {
+            Ok(seq) => {
+                let remaining = seq.len - seq.pos;
+                (remaining, Some(remaining))
+            }
+            Err(error) => {
+                let remaining = usize::from(error.is_some());
+                (remaining, Some(remaining))
+            }
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for FromObjectIter<T> where T: DeserializeOwned {}
+
+/// Resolves the constant `name` (which may be nested, e.g. `"MyApp::CONFIG"`) and deserializes
+/// its value.
+pub fn from_const<T>(name: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let object = Class::from_existing("Object")
+        .protect_send("const_get", &[RString::new_utf8(name).to_any_object()])
+        .map_err(Error::from)
+        .chain_context(|| format!("When looking up constant {}", name))?;
+    from_object(&object).chain_context(|| format!("When deserializing constant {}", name))
+}
+
+/// Reads the global variable `name` (e.g. `"$settings"`) and deserializes its value.
+pub fn from_gvar<T>(name: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut chars = name.chars();
+    let valid = chars.next() == Some('$')
+        && !name[1..].is_empty()
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("{:?} is not a valid global variable name", name).into());
+    }
+    let object = VM::eval(name)
+        .map_err(Error::from)
+        .chain_context(|| format!("When reading global variable {}", name))?;
+    from_object(&object).chain_context(|| format!("When deserializing global variable {}", name))
+}
+
+/// Evaluates `ruby_code` and deserializes the result. Errors raised by the code are returned
+/// rather than raised, with the snippet included in the error context.
+pub fn eval_into<T>(ruby_code: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let object = VM::eval(ruby_code)
+        .map_err(Error::from)
+        .chain_context(|| format!("When evaluating Ruby code: {}", snippet(ruby_code)))?;
+    from_object(&object).chain_context(|| {
+        format!(
+            "When deserializing the result of Ruby code: {}",
+            snippet(ruby_code)
+        )
+    })
+}
+
+fn snippet(code: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    let code = code.trim();
+    if code.chars().count() > MAX_CHARS {
+        format!("{}...", code.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        code.to_owned()
+    }
+}
+
+// The entries of an object deserialized as a map. Hash-like objects are read through `keys`;
+// other objects (e.g. a struct with `#[serde(flatten)]` fields read from a PORO) through `to_h`
+// if that fallback is enabled, else as their instance variables, keyed by their names without
+// the `@` and read through the object's readers.
+fn map_entries(object: &AnyObject, fallbacks: ConversionFallbacks) -> Result<MapEntries> {
+    let entries = |entries: AnyObject, values| {
+        Ok(MapEntries {
+            entries: entries.try_convert_to()?,
+            values,
+        })
+    };
+    if object.value().ty() == ValueType::Hash {
+        return entries(object.protect_send("to_a", &[])?, MapValues::Paired);
+    }
+    if object.respond_to("keys") {
+        return entries(cache::send(object, "keys", &[])?, MapValues::Fetched);
+    }
+    if fallbacks.to_h && object.respond_to("to_h") {
+        let hash = object.protect_send("to_h", &[])?;
+        return entries(hash.protect_send("to_a", &[])?, MapValues::Converted);
+    }
+    entries(
+        instance_variable_names(object)?.to_any_object(),
+        MapValues::Read,
+    )
+}
+
+struct MapEntries {
+    entries: Array,
+    values: MapValues,
+}
+
+// What the entries of a map are, and so where their values come from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MapValues {
+    // `[key, value]` pairs.
+    Paired,
+    // The `[key, value]` pairs of a fresh Hash from `to_h`, which nothing else references.
+    Converted,
+    // Keys whose values are looked up with `fetch`.
+    Fetched,
+    // The names of instance variables, whose values are read through the object's readers as
+    // for struct fields.
+    Read,
+}
+
+fn instance_variable_names(object: &AnyObject) -> Result<Array> {
+    let names = object
+        .protect_send("instance_variables", &[])?
+        .try_convert_to::<Array>()?;
+    let mut stripped = Array::with_capacity(names.length());
+    for name in names {
+        let name = name.try_convert_to::<Symbol>()?;
+        stripped.push(Symbol::new(name.to_str().trim_start_matches('@')));
+    }
+    Ok(stripped)
+}
+
+// Whether `deserialize_any` should read an object of no core type as a map of its instance
+// variables, as for (parts of) structs with `#[serde(flatten)]` fields, which serde buffers
+// through `deserialize_any`.
+fn has_instance_variables(object: &AnyObject) -> Result<bool> {
+    Ok(object
+        .protect_send("instance_variables", &[])?
+        .try_convert_to::<Array>()?
+        .length()
+        > 0)
+}
+
+fn is_numeric(object: &AnyObject) -> Result<bool> {
+    Ok(object
+        .protect_send("is_a?", &[cache::core_class("Numeric").to_any_object()])?
+        .value()
+        .is_true())
+}
+
+// Whether `object` is a `Set` (or `SortedSet`), without loading the `set` library.
+fn is_set(object: &AnyObject) -> Result<bool> {
+    let object_class = cache::core_class("Object");
+    let set = cache::symbol("Set").to_any_object();
+    if !object_class
+        .protect_send("const_defined?", std::slice::from_ref(&set))?
+        .value()
+        .is_true()
+    {
+        return Ok(false);
+    }
+    let set_class = object_class.protect_send("const_get", &[set])?;
+    Ok(object
+        .protect_send("is_a?", &[set_class])?
+        .value()
+        .is_true())
+}
+
+pub(crate) fn object_class_name(object: &AnyObject) -> Result<String> {
     let class_name = object
         .protect_public_send("class", &[])?
         .protect_public_send("name", &[])?
@@ -25,6 +328,20 @@ fn object_class_name(object: &AnyObject) -> Result<String> {
     Ok(class_name)
 }
 
+/// Converts `object` to `T` if it is one. Unlike `try_convert_to`, this doesn't allocate a
+/// `TypeError` exception for objects of other types, so it's cheap enough for probing types, e.g.
+/// while serde tries the variants of an untagged enum.
+pub(crate) fn probe<T>(object: &AnyObject) -> Option<T>
+where
+    T: VerifiedObject,
+{
+    if T::is_correct_type(object) {
+        object.try_convert_to::<T>().ok()
+    } else {
+        None
+    }
+}
+
 #[doc(hidden)]
 macro_rules! try_convert_to {
     ($object:expr, $type:ty) => {{
@@ -44,30 +361,588 @@ macro_rules! try_convert_to {
     }};
 }
 
+fn is_utf8_compatible(string: &RString) -> bool {
+    let encoding = string.encoding().name();
+    encoding == "UTF-8" || encoding == "US-ASCII"
+}
+
+thread_local! {
+    // Keyword arguments can't be passed through `protect_send`, so `encode` is called from Ruby.
+    static ENCODE_UTF8: OnceCell<AnyObject> = const { OnceCell::new() };
+}
+
+// Converts `string` to UTF-8 according to `policy`, or returns `None` if it can be read as it is:
+// UTF-8 and US-ASCII Strings with valid contents, and binary Strings, which are read as bytes.
+fn transcode_to_utf8(string: &RString, policy: InvalidEncoding) -> Result<Option<RString>> {
+    let encoding = string.encoding().name();
+    let replace = policy == InvalidEncoding::Replace;
+    let needs_transcoding = match encoding.as_str() {
+        "US-ASCII" | "ASCII-8BIT" => false,
+        "UTF-8" => replace && str::from_utf8(string.to_bytes_unchecked()).is_err(),
+        _ => true,
+    };
+    if !needs_transcoding {
+        return Ok(None);
+    }
+    let encode = ENCODE_UTF8.with(|encode| -> Result<AnyObject> {
+        if let Some(encode) = encode.get() {
+            return Ok(encode.clone());
+        }
+        let lambda = shareable_lambda(
+            "->(string, replace) do
+                if !replace then string.encode(Encoding::UTF_8)
+                elsif string.encoding == Encoding::UTF_8 then string.scrub
+                else string.encode(Encoding::UTF_8, invalid: :replace, undef: :replace)
+                end
+            end",
+        )?;
+        Ok(encode.get_or_init(|| lambda).clone())
+    })?;
+    let transcoded = encode
+        .protect_send(
+            "call",
+            &[
+                string.to_any_object(),
+                Boolean::new(replace).to_any_object(),
+            ],
+        )
+        .map_err(Error::from)
+        .chain_context(|| format!("When converting a String from {} to UTF-8", encoding))?;
+    Ok(Some(transcoded.try_convert_to::<RString>()?))
+}
+
+fn inspect(object: &AnyObject) -> String {
+    object
+        .protect_send("inspect", &[])
+        .ok()
+        .and_then(|inspect| inspect.try_convert_to::<RString>().ok())
+        .map(|inspect| inspect.to_string())
+        .unwrap_or_else(|| "<uninspectable object>".to_owned())
+}
+
+// The Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// How many of a Hash's keys `describe_missing_field` lists.
+const MISSING_FIELD_KEYS_LISTED: usize = 20;
+
+// Adds the keys actually present in `hash`, and its class, to a missing field error of the struct
+// deserialized from it, so mismatches such as a String key where a Symbol was expected under
+// `KeyNormalization::Exact` are easy to spot.
+fn describe_missing_field(hash: &AnyObject, error: Error) -> Error {
+    let field = match error.missing_field() {
+        Some(field) => field,
+        None => return error,
+    };
+    let class_name = object_class_name(hash).unwrap_or_else(|_| "Unknown class".to_owned());
+    let keys = hash
+        .protect_send("keys", &[])
+        .ok()
+        .and_then(|keys| keys.try_convert_to::<Array>().ok());
+    let keys = match keys {
+        Some(keys) if keys.length() == 0 => "none".to_owned(),
+        Some(keys) => {
+            let count = keys.length();
+            let mut listed: Vec<String> = keys
+                .into_iter()
+                .take(MISSING_FIELD_KEYS_LISTED)
+                .map(|key| inspect(&key))
+                .collect();
+            if count > MISSING_FIELD_KEYS_LISTED {
+                listed.push(format!("... ({} more)", count - MISSING_FIELD_KEYS_LISTED));
+            }
+            listed.join(", ")
+        }
+        None => "unknown".to_owned(),
+    };
+    error.chain_context(|| {
+        format!(
+            "Field `{}` was not found in the {}, whose keys are: {}",
+            field, class_name, keys
+        )
+    })
+}
+
+/// Splits an externally tagged enum value into its variant name and content: a single-entry
+/// `{ variant => content }` Hash, or any other object standing for a unit variant by its `to_s`
+/// (in which case the content is the object itself).
+pub(crate) fn split_variant(object: &AnyObject) -> Result<(String, AnyObject)> {
+    match object.value().ty() {
+        // { variant_name: variant_content } newtype variant or struct variant
+        ValueType::Hash => {
+            debug!("deserialize_enum: assuming externally tagged hash enum");
+            let variant_name_object = object
+                .protect_send("keys", &[])?
+                .protect_send("first", &[])?
+                .protect_send("to_s", &[])?;
+            let variant_name = try_convert_to!(variant_name_object, RString)?.to_string();
+            let variant_content = object
+                .protect_send("values", &[])?
+                .protect_send("first", &[])?;
+            Ok((variant_name, variant_content))
+        }
+        // "variant_name" unit variant
+        _ => {
+            debug!("deserialize_enum: assuming string like enum");
+            if let Some(symbol) = probe::<Symbol>(object) {
+                return Ok((symbol.to_string(), object.clone()));
+            }
+            let variant_name = object
+                .protect_send("to_s", &[])?
+                .try_convert_to::<RString>()?
+                .to_string();
+            Ok((variant_name, object.clone()))
+        }
+    }
+}
+
+/// How Strings which aren't valid UTF-8 are deserialized into Rust strings.
+///
+/// Strings in other encodings, such as Shift_JIS or ISO-8859-1, are always transcoded to UTF-8
+/// first. Binary (ASCII-8BIT) Strings are read as bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidEncoding {
+    /// Fail on bytes which are invalid in the String's encoding or have no UTF-8 equivalent. The
+    /// default.
+    #[default]
+    Fail,
+    /// Replace such bytes with U+FFFD REPLACEMENT CHARACTER.
+    Replace,
+}
+
+/// How Hash keys are matched against struct field names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyNormalization {
+    /// Only String keys match, and only when equal to the field name.
+    Exact,
+    /// String and Symbol keys match when equal to the field name, the default.
+    #[default]
+    SymbolOrString,
+    /// Like `SymbolOrString`, but keys are converted to snake_case first, so `"userId"`,
+    /// `:UserId` and `"user-id"` all match a `user_id` field.
+    SnakeCase,
+}
+
+impl KeyNormalization {
+    fn field_name(self, key: &AnyObject) -> Result<String> {
+        if let Some(symbol) = probe::<Symbol>(key) {
+            return Ok(match self {
+                // Never equal to a field name.
+                KeyNormalization::Exact => format!(":{}", symbol.to_str()),
+                KeyNormalization::SymbolOrString => symbol.to_string(),
+                KeyNormalization::SnakeCase => snake_case(symbol.to_str()),
+            });
+        }
+        let name = key
+            .protect_send("to_s", &[])?
+            .try_convert_to::<RString>()?
+            .to_string();
+        Ok(match self {
+            KeyNormalization::SnakeCase => snake_case(&name),
+            _ => name,
+        })
+    }
+
+    // `name` as `field_name` would normalize a String key of that value.
+    fn normalize(self, name: &str) -> String {
+        match self {
+            KeyNormalization::SnakeCase => snake_case(name),
+            _ => name.to_owned(),
+        }
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    constant_name(name).to_lowercase()
+}
+
+/// Options controlling how Ruby objects are deserialized.
+#[derive(Debug, Clone, Default)]
+pub struct DeserializerConfig {
+    /// Allow struct fields of plain Ruby objects to be read through private or protected
+    /// methods. By default only public reader methods are called.
+    pub allow_private_readers: bool,
+    /// How the keys of Hashes deserialized into structs are matched against field names.
+    pub key_normalization: KeyNormalization,
+    /// Truncate Integers which don't fit into 8, 16 or 32 bit targets (so -1 becomes
+    /// `u32::MAX`) instead of failing. Off by default.
+    pub wrapping_integers: bool,
+    /// How Strings which aren't valid UTF-8 are deserialized into Rust strings.
+    pub invalid_encoding: InvalidEncoding,
+    /// Only accept Arrays, objects converting into one with `to_ary`, and other objects (except
+    /// Strings and Hashes) responding to `length` and `[]`, as sequences. By default other
+    /// collections, such as Sets, Ranges and Enumerators, are materialized through `to_a` (or
+    /// `each`) first.
+    pub strict_sequences: bool,
+    /// Only accept Floats and Integers for floating point numbers. By default other Numerics, such
+    /// as Rationals and BigDecimals, are converted through `to_f`.
+    pub strict_floats: bool,
+    /// Where struct fields of plain Ruby objects are read from when the object has no reader
+    /// method named after the field.
+    pub reader_fallbacks: ReaderFallbacks,
+    /// How struct field names map to Hash keys and reader method names.
+    pub rename_fields: RenameAll,
+    /// How deeply Hashes, Arrays and objects may be nested.
+    pub max_depth: MaxDepth,
+    /// Which objects other than nil unit structs, such as `struct Marker;`, deserialize from.
+    pub unit_structs: UnitStructs,
+    /// What to do with `Float::NAN` and infinite Floats.
+    pub non_finite_floats: NonFiniteFloats,
+    /// Which objects deserialize into `bool`s.
+    pub bool_coercion: BoolCoercion,
+    /// Which conversion methods objects of classes other than the core ones are deserialized
+    /// through, by `deserialize_any` (and so self-describing targets such as
+    /// `serde_json::Value`) and as structs.
+    pub conversion_fallbacks: ConversionFallbacks,
+}
+
+// Configs changing a single option, for `from_object_with_options(&object, option)`.
+macro_rules! deserializer_config_from {
+    ($($field:ident: $option:ty),* $(,)?) => {
+        $(
+            impl From<$option> for DeserializerConfig {
+                fn from($field: $option) -> Self {
+                    DeserializerConfig {
+                        $field,
+                        ..DeserializerConfig::default()
+                    }
+                }
+            }
+        )*
+    };
+}
+
+deserializer_config_from! {
+    key_normalization: KeyNormalization,
+    invalid_encoding: InvalidEncoding,
+    reader_fallbacks: ReaderFallbacks,
+    rename_fields: RenameAll,
+    max_depth: MaxDepth,
+    unit_structs: UnitStructs,
+    non_finite_floats: NonFiniteFloats,
+    bool_coercion: BoolCoercion,
+    conversion_fallbacks: ConversionFallbacks,
+}
+
+/// Conversion methods tried, in order, for objects of other than the core classes, as defined by
+/// Structs, value objects and Rails models. All are enabled by default; `NONE` is the strict
+/// mode.
+///
+/// `deserialize_any` tries them before reading the object's instance variables as a map, and
+/// structs when the object has no reader method for any of their fields. Maps read from objects
+/// which aren't Hash-like only go through `to_h` if it's enabled. Times and Dates
+/// (anything responding to `iso8601`) always become ISO 8601 Strings in `deserialize_any`, and
+/// Numerics such as BigDecimals always become floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionFallbacks {
+    /// `to_h`, as defined by Structs and `OpenStruct`s.
+    pub to_h: bool,
+    /// `serializable_hash`, as defined by ActiveModel.
+    pub serializable_hash: bool,
+    /// `as_json`, as defined by ActiveSupport for most objects.
+    pub as_json: bool,
+}
+
+impl ConversionFallbacks {
+    /// Don't call any conversion methods, so objects without readers fail to deserialize.
+    pub const NONE: ConversionFallbacks = ConversionFallbacks {
+        to_h: false,
+        serializable_hash: false,
+        as_json: false,
+    };
+
+    fn methods(self) -> impl Iterator<Item = &'static str> {
+        IntoIterator::into_iter([
+            ("to_h", self.to_h),
+            ("serializable_hash", self.serializable_hash),
+            ("as_json", self.as_json),
+        ])
+        .filter(|(_, enabled)| *enabled)
+        .map(|(method, _)| method)
+    }
+}
+
+impl Default for ConversionFallbacks {
+    fn default() -> Self {
+        ConversionFallbacks {
+            to_h: true,
+            serializable_hash: true,
+            as_json: true,
+        }
+    }
+}
+
+/// A limit on how deeply collections may be nested, so that overly deep (or, when serializing,
+/// recursive) data fails with an error instead of overflowing the stack. The default is 128.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxDepth(pub usize);
+
+impl Default for MaxDepth {
+    fn default() -> Self {
+        MaxDepth(128)
+    }
+}
+
+/// Fallbacks for reading struct fields of plain Ruby objects, tried in order after the reader
+/// method named after the field. Both are enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderFallbacks {
+    /// Call the predicate method `field?`, so an `active: bool` field reads `active?`.
+    pub predicate: bool,
+    /// Read the instance variable `@field`, if it is defined.
+    pub instance_variable: bool,
+}
+
+impl ReaderFallbacks {
+    /// Only call reader methods named exactly after the field.
+    pub const NONE: ReaderFallbacks = ReaderFallbacks {
+        predicate: false,
+        instance_variable: false,
+    };
+}
+
+impl Default for ReaderFallbacks {
+    fn default() -> Self {
+        ReaderFallbacks {
+            predicate: true,
+            instance_variable: true,
+        }
+    }
+}
+
+/// Which Ruby objects deserialize into `bool`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoolCoercion {
+    /// Only `true` and `false`, the default.
+    #[default]
+    Strict,
+    /// Any object, by Ruby's truthiness: `nil` and `false` are false, and everything else
+    /// (including `0` and `""`) is true.
+    Truthy,
+    /// `true` and `false`, and the Strings or Symbols `"true"` and `"false"`.
+    Strings,
+}
+
+/// How NaN and infinite floats are treated, which many other formats (such as JSON) can't
+/// represent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFiniteFloats {
+    /// Pass them through like any other float, the default.
+    #[default]
+    Allow,
+    /// Fail with an error.
+    Error,
+    /// Treat them as nil: `None` when deserializing an `Option`, and nil when serializing.
+    /// Deserializing them as plain floats fails.
+    ConvertToNil,
+}
+
+/// Objects which unit structs deserialize from besides nil, which is what they serialize into.
+/// Both are disabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UnitStructs {
+    /// Accept an empty Hash, as produced by serializers representing unit structs as `{}`.
+    pub empty_hash: bool,
+    /// Accept a String or Symbol of the struct's name, so `Marker` reads `"Marker"` or `:Marker`.
+    pub name: bool,
+}
+
+#[derive(Clone)]
 pub struct Deserializer {
     object: AnyObject,
+    config: Rc<DeserializerConfig>,
+    // Set by `from_object_borrowed`, whose signature ties `'de` to the borrowed object.
+    borrow_strings: bool,
+    // The collections whose contents are being deserialized, outermost first, shared with child
+    // deserializers.
+    ancestors: Rc<RefCell<Vec<usize>>>,
+}
+
+// Marks a collection as being deserialized until dropped; see `Deserializer::enter`.
+struct Nesting {
+    ancestors: Rc<RefCell<Vec<usize>>>,
+}
+
+impl Drop for Nesting {
+    fn drop(&mut self) {
+        self.ancestors.borrow_mut().pop();
+    }
 }
 
 impl Deserializer {
     pub fn new<T>(object: &T) -> Self
+    where
+        T: Object,
+    {
+        Self::with_config(object, DeserializerConfig::default())
+    }
+
+    /// A `Deserializer` for `object` with the given options, which apply to every value nested
+    /// inside it too.
+    pub fn with_config<T>(object: &T, config: DeserializerConfig) -> Self
     where
         T: Object,
     {
         Self {
             object: object.to_any_object(),
+            config: Rc::new(config),
+            borrow_strings: false,
+            ancestors: Rc::default(),
         }
     }
 
+    /// Like `with_config`, but also taking a single option in place of a whole
+    /// `DeserializerConfig`.
+    pub fn with_options<T, C>(object: &T, options: C) -> Self
+    where
+        T: Object,
+        C: Into<DeserializerConfig>,
+    {
+        Self::with_config(object, options.into())
+    }
+
+    pub(crate) fn object(&self) -> &AnyObject {
+        &self.object
+    }
+
+    /// A deserializer for a nested value, sharing this deserializer's configuration.
+    pub(crate) fn child<T>(&self, object: &T) -> Self
+    where
+        T: Object,
+    {
+        Self {
+            object: object.to_any_object(),
+            config: self.config.clone(),
+            borrow_strings: self.borrow_strings,
+            ancestors: self.ancestors.clone(),
+        }
+    }
+
+    // Records that the contents of the object are being deserialized, until the returned
+    // `Nesting` is dropped. Fails for a collection which contains itself, which would otherwise
+    // recurse until the stack overflows, and for collections nested deeper than `max_depth`.
+    fn enter(&self) -> Result<Nesting> {
+        let identity = self.object.value().value;
+        let mut ancestors = self.ancestors.borrow_mut();
+        if ancestors.contains(&identity) {
+            let class_name =
+                object_class_name(&self.object).unwrap_or_else(|_| "Unknown class".to_owned());
+            return Err(
+                format!("Cannot deserialize a {} which contains itself", class_name).into(),
+            );
+        }
+        let max_depth = self.config.max_depth.0;
+        if ancestors.len() >= max_depth {
+            return Err(format!(
+                "Collections are nested more than {} levels deep (see DeserializerConfig::max_depth)",
+                max_depth
+            )
+            .into());
+        }
+        ancestors.push(identity);
+        Ok(Nesting {
+            ancestors: self.ancestors.clone(),
+        })
+    }
+
     fn protect_send(&self, method: &str, arguments: &[AnyObject]) -> Result<AnyObject> {
         Ok(self.object.protect_send(method, arguments)?)
     }
 
+    // The first of the enabled `conversion_fallbacks` the object responds to.
+    fn conversion_method(&self) -> Option<&'static str> {
+        self.config
+            .conversion_fallbacks
+            .methods()
+            .find(|method| self.object.respond_to(method))
+    }
+
+    // A `SeqAccess` over exactly `len` elements, failing upfront for sequences of other lengths
+    // rather than with a confusing error about the first element which is missing or left over.
+    fn tuple_access<F>(
+        self,
+        len: usize,
+        expected: F,
+        visitor: &dyn de::Expected,
+    ) -> Result<SeqAccess>
+    where
+        F: FnOnce() -> String,
+    {
+        let object = self.object.clone();
+        let access = SeqAccess::new(self, visitor)?;
+        if access.len != len {
+            return Err(format!(
+                "Expected {}, got {} of length {}",
+                expected(),
+                object_class_name(&object).unwrap_or_else(|_| "Unknown class".to_owned()),
+                access.len
+            )
+            .into());
+        }
+        Ok(access)
+    }
+
+    // The conversion method to deserialize the struct with `fields` from, for objects with no
+    // reader method for any of the fields.
+    fn struct_conversion_method(&self, fields: &[&str]) -> Option<&'static str> {
+        let has_reader = fields.iter().any(|field| {
+            self.object
+                .respond_to(&self.config.rename_fields.apply(field))
+        });
+        if fields.is_empty() || has_reader {
+            return None;
+        }
+        self.conversion_method()
+    }
+
+    // Deserializes the result of calling the conversion `method` in place of the object.
+    fn deserialize_converted<F, T>(&self, method: &str, deserialize: F) -> Result<T>
+    where
+        F: FnOnce(Deserializer) -> Result<T>,
+    {
+        // Entered, so that a conversion returning an object like itself can't recurse forever.
+        let _nesting = self.enter()?;
+        // Only public conversions are looked for, so only public ones are called.
+        let converted = self.object.protect_public_send(method, &[])?;
+        // The result is a fresh object which nothing else references, so its contents can't be
+        // borrowed beyond this call.
+        let mut converted_de = self.child(&converted);
+        converted_de.borrow_strings = false;
+        deserialize(converted_de)
+            .chain_context(|| format!("When deserializing the result of {}", method))
+    }
+
+    // Whether the object is a non-finite Float to be treated as nil.
+    fn is_nil_float(&self) -> bool {
+        self.config.non_finite_floats == NonFiniteFloats::ConvertToNil
+            && probe::<Float>(&self.object).is_some_and(|float| !float.to_f64().is_finite())
+    }
+
     fn deserialize_float(&self) -> Result<f64> {
         self.object
             .try_convert_to::<Float>()
             .map(|f| f.to_f64())
             .or_else(|_| self.deserialize_long().map(|n| n as f64))
             .map_err(Error::from)
+            .or_else(|error| {
+                // Other Numerics, such as Rationals, BigDecimals and Bignums, through `to_f`.
+                if self.config.strict_floats || !is_numeric(&self.object)? {
+                    return Err(error);
+                }
+                Ok(try_convert_to!(self.protect_send("to_f", &[])?, Float)?.to_f64())
+            })
             .chain_context(|| {
                 let class_name =
                     object_class_name(&self.object).unwrap_or_else(|_| "Unknown class".to_owned());
@@ -77,8 +952,83 @@ impl Deserializer {
 
     fn deserialize_long(&self) -> Result<i64> {
         debug!("deserialize_long");
-        try_convert_to!(self.object, Fixnum).map(|fixnum| fixnum.to_i64())
+        integer_to_i64(&try_convert_to!(self.object, Integer)?)
+    }
+
+    // Integers narrower than 64 bits. Unless `wrapping_integers` is set, values outside of
+    // `min..=max` are rejected rather than truncated.
+    fn deserialize_narrow_integer(&self, type_name: &str, min: i64, max: i64) -> Result<i64> {
+        let num = self.deserialize_long()?;
+        if !self.config.wrapping_integers && (num < min || num > max) {
+            return Err(format!(
+                "{} is out of range for {} (expected {}..={})",
+                num, type_name, min, max
+            )
+            .into());
+        }
+        Ok(num)
+    }
+
+    fn deserialize_unsigned_long(&self) -> Result<u64> {
+        debug!("deserialize_unsigned_long");
+        let integer = try_convert_to!(self.object, Integer)?;
+        if self.protect_send("negative?", &[])?.value().is_true() {
+            return Err(self.out_of_range("u64"));
+        }
+        if self.object.value().ty() == ValueType::Bignum && self.bit_length()? > 64 {
+            return Err(self.out_of_range("u64"));
+        }
+        Ok(integer.to_u64())
+    }
+
+    // The decimal representation of an Integer of any size.
+    fn integer_digits(&self) -> Result<String> {
+        try_convert_to!(self.object, Integer)?;
+        Ok(self
+            .protect_send("to_s", &[])?
+            .try_convert_to::<RString>()?
+            .to_string())
+    }
+
+    fn bit_length(&self) -> Result<i64> {
+        bit_length(&self.object)
     }
+
+    fn out_of_range(&self, type_name: &str) -> Error {
+        out_of_range(&self.object, type_name)
+    }
+}
+
+/// `integer` as an `i64`, failing for Bignums which don't fit rather than raising, as
+/// `Integer::to_i64` does without protection.
+pub(crate) fn integer_to_i64(integer: &Integer) -> Result<i64> {
+    if integer.value().ty() == ValueType::Bignum && bit_length(integer)? > 63 {
+        return Err(out_of_range(integer, "i64"));
+    }
+    Ok(integer.to_i64())
+}
+
+fn bit_length<O>(integer: &O) -> Result<i64>
+where
+    O: Object,
+{
+    Ok(integer
+        .protect_send("bit_length", &[])?
+        .try_convert_to::<Fixnum>()?
+        .to_i64())
+}
+
+fn out_of_range<O>(integer: &O, type_name: &str) -> Error
+where
+    O: Object,
+{
+    let value = integer
+        .protect_send("to_s", &[])
+        .ok()
+        .and_then(|s| s.try_convert_to::<RString>().ok())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "Integer".to_owned());
+    format!("{} is out of range for {}", value, type_name).into()
 }
 
 #[allow(unused_variables)]
@@ -95,16 +1045,46 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         if self.object.is_nil() {
             return self.deserialize_unit(visitor);
         }
-        let class_name = object_class_name(&self.object)?;
-        match &*class_name {
-            "Array" => self.deserialize_seq(visitor),
-            "Fixnum" | "Integer" => self.deserialize_i64(visitor),
-            "Float" => self.deserialize_f64(visitor),
-            "Hash" => self.deserialize_map(visitor),
-            "NilClass" => visitor.visit_none(),
-            "String" | "Symbol" => self.deserialize_string(visitor),
-            "TrueClass" | "FalseClass" => self.deserialize_bool(visitor),
-            _ => Err(format!("No rules to deserialize {}", class_name).into()),
+        // Dispatch on the built-in type rather than the class name, so that subclasses of core
+        // classes (e.g. `HashWithIndifferentAccess`) work too. This matters for serde's internally
+        // tagged, adjacently tagged and untagged enums, which buffer the whole value up front.
+        match self.object.value().ty() {
+            ValueType::Array => self.deserialize_seq(visitor),
+            ValueType::Fixnum => self.deserialize_i64(visitor),
+            // Bignums are too large (or small) for an i64, but positive ones may fit in a u64.
+            ValueType::Bignum => {
+                if self.protect_send("negative?", &[])?.value().is_true() {
+                    self.deserialize_i64(visitor)
+                } else {
+                    self.deserialize_u64(visitor)
+                }
+            }
+            ValueType::Float if self.is_nil_float() => visitor.visit_none(),
+            ValueType::Float => self.deserialize_f64(visitor),
+            ValueType::Hash => self.deserialize_map(visitor),
+            ValueType::Nil => visitor.visit_none(),
+            ValueType::RString | ValueType::Symbol => self.deserialize_string(visitor),
+            ValueType::True | ValueType::False => self.deserialize_bool(visitor),
+            _ if is_set(&self.object)? => self.deserialize_seq(visitor),
+            _ if self.object.respond_to("iso8601") => {
+                let iso8601 = self.protect_send("iso8601", &[])?;
+                // A fresh String, which can't be borrowed.
+                let mut iso8601_de = self.child(&iso8601);
+                iso8601_de.borrow_strings = false;
+                iso8601_de.deserialize_string(visitor)
+            }
+            _ if is_numeric(&self.object)? => self.deserialize_f64(visitor),
+            _ => match self.conversion_method() {
+                // `HashAccess` reads the entries of `to_h` itself.
+                Some("to_h") => self.deserialize_map(visitor),
+                Some(method) => self
+                    .deserialize_converted(method, |converted| converted.deserialize_any(visitor)),
+                None if has_instance_variables(&self.object)? => self.deserialize_map(visitor),
+                None => {
+                    let class_name = object_class_name(&self.object)?;
+                    Err(format!("No rules to deserialize {}", class_name).into())
+                }
+            },
         }
     }
 
@@ -113,7 +1093,20 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("Deserialize bool");
-        let o = try_convert_to!(self.object, Boolean)?.to_bool();
+        let o = match self.config.bool_coercion {
+            BoolCoercion::Truthy => !(self.object.is_nil() || self.object.value().is_false()),
+            BoolCoercion::Strings if probe::<Boolean>(&self.object).is_none() => {
+                let string = probe::<Symbol>(&self.object)
+                    .map(|symbol| symbol.to_string())
+                    .or_else(|| probe::<RString>(&self.object).map(|string| string.to_string()));
+                match string.as_deref() {
+                    Some("true") => true,
+                    Some("false") => false,
+                    _ => try_convert_to!(self.object, Boolean)?.to_bool(),
+                }
+            }
+            _ => try_convert_to!(self.object, Boolean)?.to_bool(),
+        };
         debug!("Deserialized: {}", o);
         visitor.visit_bool(o)
     }
@@ -123,7 +1116,8 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("Deserialize i8");
-        self.deserialize_i32(visitor)
+        let num = self.deserialize_narrow_integer("i8", i8::MIN.into(), i8::MAX.into())?;
+        visitor.visit_i8(num as i8)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
@@ -131,7 +1125,8 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("Deserialize i16");
-        self.deserialize_i32(visitor)
+        let num = self.deserialize_narrow_integer("i16", i16::MIN.into(), i16::MAX.into())?;
+        visitor.visit_i16(num as i16)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
@@ -139,10 +1134,8 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("Deserialize i32");
-        // let o = try_convert_to!(self.object, Fixnum)?.to_i32();
-        // visitor.visit_i32(o)
-        let o = try_convert_to!(self.object, Fixnum)?.to_i64();
-        visitor.visit_i64(o)
+        let num = self.deserialize_narrow_integer("i32", i32::MIN.into(), i32::MAX.into())?;
+        visitor.visit_i32(num as i32)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
@@ -155,12 +1148,25 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         visitor.visit_i64(num)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        debug!("deserialize_i128");
+        let digits = self.integer_digits()?;
+        let num = digits
+            .parse::<i128>()
+            .map_err(|_| self.out_of_range("i128"))?;
+        visitor.visit_i128(num)
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         debug!("Deserialize u8");
-        self.deserialize_u32(visitor)
+        let num = self.deserialize_narrow_integer("u8", 0, u8::MAX.into())?;
+        visitor.visit_u8(num as u8)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
@@ -168,7 +1174,8 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("Deserialize u16");
-        self.deserialize_u32(visitor)
+        let num = self.deserialize_narrow_integer("u16", 0, u16::MAX.into())?;
+        visitor.visit_u16(num as u16)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
@@ -176,8 +1183,8 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("Deserialize u32");
-        let o = try_convert_to!(self.object, Fixnum)?.to_i64();
-        visitor.visit_u32(o as u32)
+        let num = self.deserialize_narrow_integer("u32", 0, u32::MAX.into())?;
+        visitor.visit_u32(num as u32)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
@@ -185,8 +1192,20 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_u64");
-        let num = self.deserialize_long()?;
-        visitor.visit_u64(num as u64)
+        let num = self.deserialize_unsigned_long()?;
+        visitor.visit_u64(num)
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        debug!("deserialize_u128");
+        let digits = self.integer_digits()?;
+        let num = digits
+            .parse::<u128>()
+            .map_err(|_| self.out_of_range("u128"))?;
+        visitor.visit_u128(num)
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
@@ -203,6 +1222,13 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
     {
         debug!("Deserialize f64");
         let o = self.deserialize_float()?;
+        if !o.is_finite() && self.config.non_finite_floats != NonFiniteFloats::Allow {
+            return Err(format!(
+                "{} is not a finite number (see DeserializerConfig::non_finite_floats)",
+                o
+            )
+            .into());
+        }
         debug!("Deserialized: {}", o);
         visitor.visit_f64(o)
     }
@@ -220,13 +1246,33 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_str: {:?}", self.object);
-        let s = self
-            .object
-            .protect_send("to_s", &[])?
-            .try_convert_to::<RString>()?;
+        // Symbols are read directly from their name, without allocating a String through `to_s`.
+        if let Some(symbol) = probe::<Symbol>(&self.object) {
+            return visitor.visit_str(symbol.to_str());
+        }
+        let (s, borrowable) = match probe::<RString>(&self.object) {
+            Some(s) => (s, self.borrow_strings),
+            // The result of `to_s` is a fresh String, which can't be borrowed.
+            None => (
+                self.object
+                    .protect_send("to_s", &[])?
+                    .try_convert_to::<RString>()?,
+                false,
+            ),
+        };
+        let (s, borrowable) = match transcode_to_utf8(&s, self.config.invalid_encoding)? {
+            Some(transcoded) => (transcoded, false),
+            None => (s, borrowable),
+        };
         let b = s.to_bytes_unchecked();
-        if let Ok(s) = str::from_utf8(b) {
-            visitor.visit_str(s)
+        if let Ok(string) = str::from_utf8(b) {
+            if borrowable && s.is_frozen() && is_utf8_compatible(&s) {
+                // Safety: `from_object_borrowed` ties `'de` to the object owning this String,
+                // whose contents can't change as it's frozen.
+                let string: &'de str = unsafe { &*(string as *const str) };
+                return visitor.visit_borrowed_str(string);
+            }
+            visitor.visit_str(string)
         } else {
             visitor.visit_bytes(b)
         }
@@ -237,16 +1283,9 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_string: {:?}", self.object);
-        let s = self
-            .object
-            .protect_send("to_s", &[])?
-            .try_convert_to::<RString>()?;
-        let b = s.to_vec_u8_unchecked();
-        if str::from_utf8(&b).is_ok() {
-            visitor.visit_string(unsafe { String::from_utf8_unchecked(b) }) // SAFETY: we just checked that `b` is valid UTF-8
-        } else {
-            visitor.visit_byte_buf(b)
-        }
+        // Visiting the borrowed bytes costs the same single copy for a fresh `String`, and lets
+        // `deserialize_in_place` copy into an existing buffer instead.
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -254,6 +1293,7 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_bytes: {:?}", self.object);
+        // The raw bytes are returned whatever the String's encoding.
         let s = try_convert_to!(self.object, RString)?;
         visitor.visit_bytes(s.to_bytes_unchecked())
     }
@@ -271,7 +1311,7 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
     where
         V: Visitor<'de>,
     {
-        if self.object.is_nil() {
+        if self.object.is_nil() || self.is_nil_float() {
             debug!("deserialize_option: visit_none");
             visitor.visit_none()
         } else {
@@ -297,7 +1337,22 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_unit_struct: {}", name);
-        visitor.visit_unit()
+        let object = &self.object;
+        let accepted = self.config.unit_structs;
+        let is_name = |string: &str| accepted.name && string == name;
+        if object.is_nil()
+            || (accepted.empty_hash && probe::<Hash>(object).is_some_and(|hash| hash.length() == 0))
+            || probe::<Symbol>(object).is_some_and(|symbol| is_name(symbol.to_str()))
+            || probe::<RString>(object).is_some_and(|string| is_name(string.to_str()))
+        {
+            return visitor.visit_unit();
+        }
+        let class_name = object_class_name(object).unwrap_or_else(|_| "Unknown class".to_owned());
+        Err(format!(
+            "Expected nil for unit struct {}, got an instance of {}",
+            name, class_name
+        )
+        .into())
     }
 
     fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
@@ -305,6 +1360,10 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_newtype_struct: {}", name);
+        if name == raw::RAW_OBJECT_TOKEN {
+            raw::stash_deserializer(self);
+            return visitor.visit_unit();
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -313,7 +1372,7 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_seq");
-        let s = SeqAccess::new(self.object)?;
+        let s = SeqAccess::new(self, &visitor)?;
         visitor.visit_seq(s)
     }
 
@@ -322,7 +1381,7 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_tuple");
-        let s = SeqAccess::new(self.object)?;
+        let s = self.tuple_access(len, || format!("tuple of {}", len), &visitor)?;
         visitor.visit_seq(s)
     }
 
@@ -335,7 +1394,13 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
     where
         V: Visitor<'de>,
     {
-        Err(ErrorKind::NotImplemented("Deserializer::deserialize_tuple_struct").into())
+        debug!("deserialize_tuple_struct: {}", name);
+        let s = self.tuple_access(
+            len,
+            || format!("tuple struct {} of {}", name, len),
+            &visitor,
+        )?;
+        visitor.visit_seq(s)
     }
 
     fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
@@ -356,17 +1421,25 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
         V: Visitor<'de>,
     {
         debug!("deserialize_struct: {}, fields: {:?}", name, fields);
-        if self
-            .object
-            .protect_send("is_a?", &[Class::from_existing("Hash").to_any_object()])?
-            .try_convert_to::<Boolean>()?
-            .to_bool()
-        {
+        // Instances of Hash and its subclasses are all T_HASH; only proxies need asking.
+        let is_hash = self.object.value().ty() == ValueType::Hash
+            || self
+                .object
+                .protect_send("is_a?", &[cache::core_class("Hash").to_any_object()])?
+                .try_convert_to::<Boolean>()?
+                .to_bool();
+        if is_hash {
             debug!("deserialize_struct: as a Hash");
-            visitor.visit_map(HashAccess::new(&mut self)?)
+            let result = visitor.visit_map(HashAccess::for_struct(&mut self, fields)?);
+            result.map_err(|error| describe_missing_field(&self.object, error))
+        } else if let Some(method) = self.struct_conversion_method(fields) {
+            debug!("deserialize_struct: through {}", method);
+            self.deserialize_converted(method, |converted| {
+                converted.deserialize_struct(name, fields, visitor)
+            })
         } else {
             debug!("deserialize_struct: as an Object");
-            visitor.visit_map(ObjectAccess::new(&mut self, fields))
+            visitor.visit_map(ObjectAccess::new(&mut self, fields)?)
         }
     }
 
@@ -383,7 +1456,7 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
             "deserialize_enum name: {:?}, variants: {:?}",
             name, variants
         );
-        visitor.visit_enum(EnumAccess::new(self.object))
+        visitor.visit_enum(EnumAccess::new(self))
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
@@ -410,19 +1483,172 @@ impl<'de, 'a> de::Deserializer<'de> for Deserializer {
     }
 }
 
+// Reading the fields of plain Ruby objects through their reader methods, for `ObjectAccess` and
+// for objects read as maps of their instance variables.
+impl Deserializer {
+    // Whether `name` can be called as a reader on the object. Unless `allow_private_readers` is
+    // set only public methods count.
+    fn responds_to(&self, name: &str) -> bool {
+        if self.config.allow_private_readers {
+            self.responds_to_privately(name)
+        } else {
+            self.object.respond_to(name)
+        }
+    }
+
+    fn responds_to_privately(&self, name: &str) -> bool {
+        self.object
+            .protect_public_send(
+                "respond_to?",
+                &[
+                    Symbol::new(name).to_any_object(),
+                    Boolean::new(true).to_any_object(),
+                ],
+            )
+            .map(|found| found.value().is_true())
+            .unwrap_or(false)
+    }
+
+    fn call_reader(&self, name: &str) -> Result<AnyObject> {
+        if self.config.allow_private_readers {
+            self.protect_send(name, &[])
+        } else {
+            Ok(self.object.protect_public_send(name, &[])?)
+        }
+    }
+
+    // The reader methods `read_field` looks for for the field `identifier`: the `identifier`
+    // method itself, then (as configured in `reader_fallbacks`) the `identifier?` predicate. The
+    // second value is the `@identifier` instance variable, if it is looked for as well.
+    fn readers(&self, identifier: &str) -> (Vec<String>, Option<String>) {
+        let fallbacks = self.config.reader_fallbacks;
+        // Names such as `active?` already are predicates, and can't be instance variables.
+        let plain_name = !identifier.ends_with(['?', '!', '=']);
+        let mut methods = vec![identifier.to_owned()];
+        if fallbacks.predicate && plain_name {
+            methods.push(format!("{}?", identifier));
+        }
+        let instance_variable = if fallbacks.instance_variable && plain_name {
+            Some(format!("@{}", identifier))
+        } else {
+            None
+        };
+        (methods, instance_variable)
+    }
+
+    // Where the field `identifier` can be read from: the first of its `readers` which exists.
+    fn find_reader(&self, identifier: &str) -> Result<Option<FieldReader>> {
+        let (methods, instance_variable) = self.readers(identifier);
+        if let Some(method) = methods.into_iter().find(|method| self.responds_to(method)) {
+            return Ok(Some(FieldReader::Method(method)));
+        }
+        if let Some(instance_variable) = instance_variable {
+            let name = Symbol::new(&instance_variable).to_any_object();
+            let defined =
+                self.protect_send("instance_variable_defined?", std::slice::from_ref(&name))?;
+            if defined.value().is_true() {
+                return Ok(Some(FieldReader::InstanceVariable(name)));
+            }
+        }
+        Ok(None)
+    }
+}
+
 struct ObjectAccess<'a> {
     de: &'a mut Deserializer,
     fields: &'a [&'a str],
     pos: usize,
+    _nesting: Nesting,
 }
 
 impl<'a> ObjectAccess<'a> {
-    fn new(de: &'a mut Deserializer, fields: &'a [&'a str]) -> Self {
+    fn new(de: &'a mut Deserializer, fields: &'a [&'a str]) -> Result<Self> {
         debug!("ObjectAccess fields: {:?}", fields);
-        Self { de, fields, pos: 0 }
+        let nesting = de.enter()?;
+        Ok(Self {
+            de,
+            fields,
+            pos: 0,
+            _nesting: nesting,
+        })
+    }
+
+    // Reads the value of the field `identifier` through its first existing reader, so errors
+    // raised by a reader are reported rather than skipped over.
+    fn read_field(&self, identifier: &str) -> Result<AnyObject> {
+        match self.de.find_reader(identifier)? {
+            Some(FieldReader::Method(method)) => return self.de.call_reader(&method),
+            Some(FieldReader::InstanceVariable(name)) => {
+                return self.de.protect_send("instance_variable_get", &[name])
+            }
+            None => {}
+        }
+
+        // Objects handling readers through `method_missing` may not implement
+        // `respond_to_missing?`, so call the reader anyway before giving up.
+        let error = match self.de.call_reader(identifier) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        let class_name =
+            object_class_name(&self.de.object).unwrap_or_else(|_| "Unknown class".to_owned());
+        let (methods, instance_variable) = self.de.readers(identifier);
+        let mut tried: Vec<String> = methods
+            .iter()
+            .map(|method| format!("method '{}'", method))
+            .collect();
+        tried.extend(
+            instance_variable
+                .map(|instance_variable| format!("instance variable '{}'", instance_variable)),
+        );
+        let private_reader = methods.iter().find(|method| {
+            !self.de.config.allow_private_readers && self.de.responds_to_privately(method)
+        });
+        let mut message = format!(
+            "Could not read field '{}' of {} (tried {})",
+            identifier,
+            class_name,
+            tried.join(", ")
+        );
+        match private_reader {
+            Some(reader) => message.push_str(&format!(
+                "; reader method '{}' is not public (set allow_private_readers to call it)",
+                reader
+            )),
+            None => message.push_str(&format!("; no method '{}' is defined", identifier)),
+        }
+        let unreadable = self.unreadable_later_fields()?;
+        if !unreadable.is_empty() {
+            message.push_str(&format!(
+                "\nThe fields {} have no reader either",
+                unreadable.join(", ")
+            ));
+        }
+        Err(error.chain_context(|| message))
+    }
+
+    // The fields after the current one which have none of their `readers`, so that adapting a
+    // Ruby object to a struct reports all of its missing readers at once. Only checked once a
+    // field failed, to keep the sweep off the happy path; readers which only `method_missing`
+    // handles are listed too, as they can't be found without calling them.
+    fn unreadable_later_fields(&self) -> Result<Vec<String>> {
+        let mut unreadable = Vec::new();
+        for field in &self.fields[self.pos + 1..] {
+            let identifier = self.de.config.rename_fields.apply(field);
+            if self.de.find_reader(&identifier)?.is_none() {
+                unreadable.push(format!("'{}'", identifier));
+            }
+        }
+        Ok(unreadable)
     }
 }
 
+enum FieldReader {
+    Method(String),
+    // The name of the instance variable, as a Symbol.
+    InstanceVariable(AnyObject),
+}
+
 impl<'de, 'a> MapAccess<'de> for ObjectAccess<'a> {
     type Error = Error;
 
@@ -446,34 +1672,110 @@ impl<'de, 'a> MapAccess<'de> for ObjectAccess<'a> {
         V: DeserializeSeed<'de>,
     {
         let identifier = self.fields[self.pos];
-        let field_object = self
-            .de
-            .protect_send(identifier, &[])
-            .chain_context(|| format!("While deserializing {:?}", identifier))?;
+        let segment = || PathSegment::Field(identifier.to_owned());
+        let reader = self.de.config.rename_fields.apply(identifier);
+        let field_object = self.read_field(&reader).map_err(|e| e.within(segment()))?;
         debug!(
             "next_value_seed: field: {} ({:?})",
             identifier, field_object
         );
         self.pos += 1;
+        // The reader's result may be a fresh object which nothing else references, so its
+        // contents can't be borrowed beyond this call.
+        let mut field_de = self.de.child(&field_object);
+        field_de.borrow_strings = false;
         // Deserialize a map value.
-        seed.deserialize(Deserializer::new(&field_object))
-            .chain_context(|| format!("While deserializing {}", identifier))
+        seed.deserialize(field_de).map_err(|e| e.within(segment()))
     }
 }
 
 struct SeqAccess {
-    arr: AnyObject,
+    de: Deserializer,
+    // Set for plain Arrays, whose elements are read directly instead of through `[]` calls.
+    array: Option<Array>,
     pos: usize,
     len: usize,
+    _nesting: Nesting,
 }
 
 impl SeqAccess {
-    fn new(arr: AnyObject) -> Result<Self> {
-        let len = arr
-            .protect_send("length", &[])?
+    // `expected` describes the sequence the caller wants, for the error if the object isn't one.
+    fn new(mut de: Deserializer, expected: &dyn de::Expected) -> Result<Self> {
+        // Entered before materializing, as the Array is a new object.
+        let nesting = de.enter()?;
+        if de.object.value().ty() == ValueType::Array {
+            let array = Array::from(de.object.value());
+            return Ok(Self::for_array(de, array, nesting));
+        }
+        if de.object.respond_to("to_ary") {
+            let array = de
+                .protect_send("to_ary", &[])?
+                .try_convert_to::<Array>()
+                .map_err(Error::from)
+                .chain_context(|| "When converting an object to an Array with to_ary")?;
+            // As for materialized collections, the Array and its elements may be fresh.
+            let mut de = de.child(&array);
+            de.borrow_strings = false;
+            return Ok(Self::for_array(de, array, nesting));
+        }
+        // Collections such as `Set`, Ranges, Enumerators or `ActiveRecord::Relation` can't be
+        // indexed, so they're materialized into an Array.
+        if !de.config.strict_sequences && !de.object.is_nil() {
+            let materialized = if de.object.respond_to("to_a") {
+                Some(de.protect_send("to_a", &[])?)
+            } else if de.object.respond_to("each") {
+                let each = cache::symbol("each").to_any_object();
+                let enumerator = de.protect_send("enum_for", &[each])?;
+                Some(enumerator.protect_send("to_a", &[])?)
+            } else {
+                None
+            };
+            if let Some(materialized) = materialized {
+                let array = materialized
+                    .try_convert_to::<Array>()
+                    .map_err(Error::from)
+                    .chain_context(|| "When materializing a collection into an Array")?;
+                // The elements may be fresh objects (e.g. yielded by `each`) which nothing but
+                // the Array references, so their contents can't be borrowed beyond this call.
+                let mut de = de.child(&array);
+                de.borrow_strings = false;
+                return Ok(Self::for_array(de, array, nesting));
+            }
+        }
+        // Strings and Hashes respond to `length` and `[]` too, but aren't sequences of elements.
+        let indexable = !matches!(
+            de.object.value().ty(),
+            ValueType::Nil | ValueType::RString | ValueType::Symbol | ValueType::Hash
+        ) && de.object.respond_to("length")
+            && de.object.respond_to("[]");
+        if !indexable {
+            let class_name =
+                object_class_name(&de.object).unwrap_or_else(|_| "Unknown class".to_owned());
+            return Err(format!("Expected {}, got an instance of {}", expected, class_name).into());
+        }
+        let len = cache::send(&de.object, "length", &[])?
             .try_convert_to::<Fixnum>()?
             .to_i64() as usize;
-        Ok(Self { arr, len, pos: 0 })
+        // Nothing but this deserialization may reference the results of `[]` either.
+        de.borrow_strings = false;
+        Ok(Self {
+            de,
+            array: None,
+            len,
+            pos: 0,
+            _nesting: nesting,
+        })
+    }
+
+    fn for_array(de: Deserializer, array: Array, nesting: Nesting) -> Self {
+        let len = array.length();
+        Self {
+            de,
+            array: Some(array),
+            len,
+            pos: 0,
+            _nesting: nesting,
+        }
     }
 }
 
@@ -488,11 +1790,20 @@ impl<'de> de::SeqAccess<'de> for SeqAccess {
         if self.pos == self.len {
             return Ok(None);
         }
-        let element = self
-            .arr
-            .protect_send("[]", &[Fixnum::new(self.pos as i64).to_any_object()])?;
+        let index = self.pos;
         self.pos += 1;
-        seed.deserialize(Deserializer::new(&element)).map(Some)
+        let element = match &self.array {
+            Some(array) => array.at(index as i64),
+            None => cache::send(
+                &self.de.object,
+                "[]",
+                &[Fixnum::new(index as i64).to_any_object()],
+            )
+            .map_err(|e| Error::from(e).within(PathSegment::Index(index)))?,
+        };
+        seed.deserialize(self.de.child(&element))
+            .map(Some)
+            .map_err(|e| e.within(PathSegment::Index(index)))
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -502,27 +1813,130 @@ impl<'de> de::SeqAccess<'de> for SeqAccess {
 
 struct HashAccess<'a> {
     de: &'a mut Deserializer,
-    keys: Array,
+    // For Hashes, a snapshot of the `[key, value]` pairs taken with a single `to_a` call;
+    // otherwise the `keys`, whose values are looked up with `fetch`, or the names of instance
+    // variables. Held on the stack for the duration of `deserialize_map`, where the GC's
+    // conservative stack scan keeps the entries alive and pinned.
+    entries: Array,
+    values: MapValues,
     current_key: AnyObject,
+    current_value: Option<AnyObject>,
+    // The normalized field name of `current_key`, for struct fields.
+    current_field: Option<String>,
     pos: usize,
     len: usize,
+    // Whether keys are struct field names, normalized according to the `KeyNormalization`.
+    struct_fields: bool,
+    // The struct's fields, whose renamed keys are mapped back to them.
+    fields: &'static [&'static str],
+    _nesting: Nesting,
 }
 
 impl<'a> HashAccess<'a> {
     fn new(de: &'a mut Deserializer) -> Result<Self> {
-        let keys = de
-            .object
-            .protect_send("keys", &[])?
-            .try_convert_to::<Array>()?;
-        let len = keys.length();
+        let nesting = de.enter()?;
+        let MapEntries { entries, values } =
+            map_entries(&de.object, de.config.conversion_fallbacks)?;
+        if values != MapValues::Paired {
+            // The keys and values of the converted Hash, the keys and `fetch` results of other
+            // objects, and the results of reader methods are rooted by nothing but this
+            // deserialization, so their contents can't be borrowed beyond it.
+            de.borrow_strings = false;
+        }
+        let len = entries.length();
         Ok(Self {
             de,
-            keys,
+            entries,
+            values,
             len,
             current_key: NilClass::new().to_any_object(),
+            current_value: None,
+            current_field: None,
             pos: 0,
+            struct_fields: false,
+            fields: &[],
+            _nesting: nesting,
+        })
+    }
+
+    fn for_struct(de: &'a mut Deserializer, fields: &'static [&'static str]) -> Result<Self> {
+        Ok(Self {
+            struct_fields: true,
+            fields,
+            ..Self::new(de)?
         })
     }
+
+    // An error for the key `name` matching none of the struct's fields, suggesting the closest
+    // field's key.
+    fn unknown_field(&self, name: &str) -> Error {
+        let config = &self.de.config;
+        let keys: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| config.rename_fields.apply(field).into_owned())
+            .collect();
+        let closest = keys
+            .iter()
+            .map(|key| {
+                (
+                    edit_distance(name, &config.key_normalization.normalize(key)),
+                    key,
+                )
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .filter(|(distance, _)| *distance <= (name.chars().count() / 3).max(1));
+        match closest {
+            Some((_, key)) => format!("unknown field '{}', did you mean '{}'?", name, key).into(),
+            None if keys.is_empty() => {
+                format!("unknown field '{}', there are no fields", name).into()
+            }
+            None => format!(
+                "unknown field '{}', expected one of '{}'",
+                name,
+                keys.join("', '")
+            )
+            .into(),
+        }
+    }
+
+    // The field whose renamed key is `name`, or `name` itself.
+    fn unrename(&self, name: String) -> String {
+        let config = &self.de.config;
+        if config.rename_fields.is_none() {
+            return name;
+        }
+        self.fields
+            .iter()
+            .find(|field| {
+                config
+                    .key_normalization
+                    .normalize(&config.rename_fields.apply(field))
+                    == name
+            })
+            .map_or(name, |field| (*field).to_owned())
+    }
+
+    // The value of the instance variable named by the current key, read through the object's
+    // readers for the name (as configured by `reader_fallbacks` and `allow_private_readers`),
+    // else directly.
+    fn read_instance_variable(&self) -> Result<AnyObject> {
+        let name = Symbol::from(self.current_key.value());
+        if let Some(FieldReader::Method(method)) = self.de.find_reader(name.to_str())? {
+            return self.de.call_reader(&method);
+        }
+        let instance_variable = Symbol::new(&format!("@{}", name.to_str())).to_any_object();
+        self.de
+            .protect_send("instance_variable_get", &[instance_variable])
+    }
+
+    // The path segment of the current entry's value.
+    fn segment(&self) -> PathSegment {
+        match &self.current_field {
+            Some(name) => PathSegment::Field(name.clone()),
+            None => PathSegment::Key(inspect(&self.current_key)),
+        }
+    }
 }
 
 impl<'de, 'a> MapAccess<'de> for HashAccess<'a> {
@@ -536,24 +1950,71 @@ impl<'de, 'a> MapAccess<'de> for HashAccess<'a> {
         if self.pos == self.len {
             return Ok(None);
         }
-        self.current_key = self.keys.at(self.pos as i64);
+        let entry = self.entries.at(self.pos as i64);
+        match self.values {
+            MapValues::Paired | MapValues::Converted => {
+                let pair = Array::from(entry.value());
+                self.current_key = pair.at(0);
+                self.current_value = Some(pair.at(1));
+            }
+            MapValues::Fetched | MapValues::Read => self.current_key = entry,
+        }
         debug!("next_key_seed {:?} pos: {}", self.current_key, self.pos);
-        seed.deserialize(Deserializer::new(&self.current_key))
-            .map(Some)
+        if self.struct_fields {
+            let name = self
+                .de
+                .config
+                .key_normalization
+                .field_name(&self.current_key)?;
+            let name = self.unrename(name);
+            self.current_field = Some(name.clone());
+            // Field identifiers only reject names which aren't fields, with
+            // `deny_unknown_fields`.
+            return seed
+                .deserialize(de::IntoDeserializer::<Error>::into_deserializer(
+                    name.clone(),
+                ))
+                .map(Some)
+                .map_err(|_| self.unknown_field(&name));
+        }
+        if self.values == MapValues::Read {
+            // Instance variables are named on the Ruby side, and the fields they're for aren't
+            // known here, so the name is mapped back as far as `rename_fields` allows.
+            let name = Symbol::from(self.current_key.value());
+            let name = self.de.config.rename_fields.unapply(name.to_str());
+            return seed
+                .deserialize(de::IntoDeserializer::<Error>::into_deserializer(
+                    name.into_owned(),
+                ))
+                .map(Some);
+        }
+        seed.deserialize(self.de.child(&self.current_key)).map(Some)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where
         V: DeserializeSeed<'de>,
     {
-        let field_object = self
-            .de
-            .protect_send("fetch", &[self.current_key.clone()])
-            .chain_context(|| format!("While deserializing {:?}", self.current_key.clone()))?;
+        let field_object = match self.current_value.take() {
+            Some(value) => value,
+            None if self.values == MapValues::Read => match self.read_instance_variable() {
+                Ok(value) => value,
+                Err(e) => return Err(e.within(self.segment())),
+            },
+            None => match cache::send(
+                &self.de.object,
+                "fetch",
+                std::slice::from_ref(&self.current_key),
+            ) {
+                Ok(value) => value,
+                Err(e) => return Err(Error::from(e).within(self.segment())),
+            },
+        };
         debug!("next_value_seed: field ({:?})", field_object);
         self.pos += 1;
         // Deserialize a map value.
-        seed.deserialize(Deserializer::new(&field_object))
+        seed.deserialize(self.de.child(&field_object))
+            .map_err(|e| e.within(self.segment()))
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -561,14 +2022,13 @@ impl<'de, 'a> MapAccess<'de> for HashAccess<'a> {
     }
 }
 
-#[derive(Debug)]
 struct EnumAccess {
-    object: AnyObject,
+    de: Deserializer,
 }
 
-impl<'a> EnumAccess {
-    fn new(object: AnyObject) -> Self {
-        Self { object }
+impl EnumAccess {
+    fn new(de: Deserializer) -> Self {
+        Self { de }
     }
 }
 
@@ -581,49 +2041,21 @@ impl<'de> de::EnumAccess<'de> for EnumAccess {
         V: DeserializeSeed<'de>,
     {
         use serde::de::IntoDeserializer;
-        let class_name = object_class_name(&self.object)?;
-        let (variant_name, variant_content) = match &*class_name {
-            // { variant_name: variant_content } newtype variant or struct variant
-            "Hash" => {
-                debug!("deserialize_enum: assuming externally tagged hash enum");
-                let variant_name_object = self
-                    .object
-                    .protect_send("keys", &[])?
-                    .protect_send("first", &[])?
-                    .protect_send("to_s", &[])?;
-                let variant_name = try_convert_to!(variant_name_object, RString)?.to_string();
-                let variant_content = self
-                    .object
-                    .protect_send("values", &[])?
-                    .protect_send("first", &[])?;
-                (variant_name, variant_content)
-            }
-            // "variant_name" unit variant
-            _ => {
-                debug!("deserialize_enum: assuming string like enum");
-                (
-                    self.object
-                        .protect_send("to_s", &[])?
-                        .try_convert_to::<RString>()?
-                        .to_string(),
-                    self.object,
-                )
-            }
-        };
+        let object = &self.de.object;
+        let (variant_name, variant_content) = split_variant(object)?;
         debug!("variant_seed: {}", variant_name);
         seed.deserialize(variant_name.into_deserializer())
-            .map(|variant| (variant, VariantAccess::new(variant_content)))
+            .map(|variant| (variant, VariantAccess::new(self.de.child(&variant_content))))
     }
 }
 
-#[derive(Debug)]
 struct VariantAccess {
-    object: AnyObject,
+    de: Deserializer,
 }
 
 impl VariantAccess {
-    fn new(object: AnyObject) -> Self {
-        Self { object }
+    fn new(de: Deserializer) -> Self {
+        Self { de }
     }
 }
 
@@ -640,22 +2072,38 @@ impl<'de> de::VariantAccess<'de> for VariantAccess {
         T: de::DeserializeSeed<'de>,
     {
         debug!("newtype_variant_seed");
-        seed.deserialize(Deserializer::new(&self.object))
+        seed.deserialize(self.de)
     }
 
-    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        debug!("tuple_variant");
-        Err(ErrorKind::NotImplemented("VariantAccess::tuple_variant").into())
+        debug!("tuple_variant: len: {}", len);
+        // { variant_name => [field, ...] }
+        self.de
+            .object
+            .try_convert_to::<Array>()
+            .map_err(Error::from)
+            .chain_context(|| "When deserializing the fields of a tuple variant")?;
+        let seq = self
+            .de
+            .tuple_access(len, || format!("tuple variant of {}", len), &visitor)?;
+        visitor.visit_seq(seq)
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        debug!("struct_variant");
-        Err(ErrorKind::NotImplemented("VariantAccess::struct_variant").into())
+        debug!("struct_variant: fields: {:?}", fields);
+        // { variant_name => { field => value, ... } }
+        let mut de = self.de;
+        de.object
+            .try_convert_to::<Hash>()
+            .map_err(Error::from)
+            .chain_context(|| "When deserializing the fields of a struct variant")?;
+        let result = visitor.visit_map(HashAccess::for_struct(&mut de, fields)?);
+        result.map_err(|error| describe_missing_field(&de.object, error))
     }
 }
@@ -1,6 +1,10 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::OnceCell;
 use std::fmt;
 
-use rutie::{self, Exception, Object};
+use rutie::{self, Array, Exception, Fixnum, Hash, Object, RString, Symbol};
+
+use crate::ractor::shareable_lambda;
 
 pub enum ErrorKind {
     Message(String),
@@ -35,9 +39,60 @@ impl fmt::Debug for ErrorKind {
     }
 }
 
+/// One step on the way to a value inside a Ruby structure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A struct field, read from a Hash key or a reader method.
+    Field(String),
+    /// An element of an Array.
+    Index(usize),
+    /// The value of a Hash entry, identified by the key's `inspect` output.
+    Key(String),
+}
+
+/// The location of a value inside a Ruby structure, e.g. `user.addresses[2].postcode`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+}
+
+impl Path {
+    /// The segments of the path, outermost first. Empty for the top-level value.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.segments.is_empty() {
+            return write!(f, ".");
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+                PathSegment::Key(key) => write!(f, "[{}]", key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct Error {
     kind: ErrorKind,
     context: Vec<String>,
+    // Innermost segment first, as segments are added while the error propagates outwards.
+    path: Vec<PathSegment>,
+    // Boxed to keep `Result`s small.
+    backtrace: Box<Backtrace>,
+    // Set for serde's "missing field" errors, which the `Deserializer` adds details to.
+    missing_field: Option<&'static str>,
 }
 
 impl Error {
@@ -50,11 +105,98 @@ impl Error {
         self
     }
 
+    /// Where in the deserialized Ruby structure the error occurred.
+    pub fn path(&self) -> Path {
+        Path {
+            segments: self.path.iter().rev().cloned().collect(),
+        }
+    }
+
+    // Records that the error occurred inside `segment` of the value being deserialized.
+    pub(crate) fn within(mut self, segment: PathSegment) -> Self {
+        self.path.push(segment);
+        self
+    }
+
+    // The struct field this error reports as missing, unless it has already propagated out of the
+    // struct missing it.
+    pub(crate) fn missing_field(&self) -> Option<&'static str> {
+        self.missing_field.filter(|_| self.path.is_empty())
+    }
+
+    /// Whether this error is a Ruby `Interrupt`, `SignalException` or `SystemExit`. These are
+    /// re-raised unchanged by `into_exception`, without the error's context, and shouldn't be
+    /// recovered from.
+    pub fn is_interrupt(&self) -> bool {
+        match self.kind {
+            RutieException(ref exception) => is_interrupt(exception),
+            _ => false,
+        }
+    }
+
+    /// The Rust backtrace captured when the error was created. Like `std::backtrace::Backtrace`,
+    /// it's only captured if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// The error's details as a Ruby Hash, for Ruby code which reports errors in a structured
+    /// way rather than by their message:
+    ///
+    /// ```text
+    /// {
+    ///   kind: :message,                  # or :ruby_exception, :not_implemented
+    ///   message: "invalid type: ...",    # without context and path
+    ///   context: ["When deserializing arg: user", ...],  # outermost last
+    ///   path: ["addresses", 2, "postcode"],              # outermost first
+    /// }
+    /// ```
+    ///
+    /// `into_exception` attaches this to the exceptions it raises, as `#rutie_serde_details`.
+    pub fn to_ruby_hash(&self) -> Hash {
+        let (kind, message) = match self.kind {
+            Message(ref msg) => ("message", msg.clone()),
+            RutieException(ref exception) => ("ruby_exception", exception.message()),
+            NotImplemented(description) => ("not_implemented", description.to_owned()),
+        };
+        let context: Array = self
+            .context
+            .iter()
+            .map(|frame| RString::new_utf8(frame).to_any_object())
+            .collect();
+        let path: Array = self
+            .path
+            .iter()
+            .rev()
+            .map(|segment| match segment {
+                PathSegment::Field(name) | PathSegment::Key(name) => {
+                    RString::new_utf8(name).to_any_object()
+                }
+                PathSegment::Index(index) => Fixnum::new(*index as i64).to_any_object(),
+            })
+            .collect();
+        let mut hash = Hash::new();
+        hash.store(Symbol::new("kind"), Symbol::new(kind));
+        hash.store(Symbol::new("message"), RString::new_utf8(&message));
+        hash.store(Symbol::new("context"), context);
+        hash.store(Symbol::new("path"), path);
+        hash
+    }
+
     fn describe_context(&self) -> String {
-        if self.context.is_empty() {
+        let path = if self.path.is_empty() {
             "".to_owned()
         } else {
-            format!("\nContext from Rust:\n - {}", self.context.join("\n - "))
+            format!("\nAt path: {}", self.path())
+        };
+        if self.context.is_empty() {
+            path
+        } else {
+            format!(
+                "{}\nContext from Rust:\n - {}",
+                path,
+                self.context.join("\n - ")
+            )
         }
     }
 }
@@ -86,6 +228,9 @@ impl From<ErrorKind> for Error {
         Error {
             kind,
             context: vec![],
+            path: vec![],
+            backtrace: Box::new(Backtrace::capture()),
+            missing_field: None,
         }
     }
 }
@@ -124,6 +269,12 @@ impl serde::de::Error for Error {
     {
         format!("{}", msg).into()
     }
+
+    fn missing_field(field: &'static str) -> Self {
+        let mut error = Self::custom(format_args!("missing field `{}`", field));
+        error.missing_field = Some(field);
+        error
+    }
 }
 
 impl serde::ser::Error for Error {
@@ -141,10 +292,14 @@ pub trait IntoException {
 
 impl IntoException for Error {
     fn into_exception(self, default_class: rutie::Class) -> rutie::AnyException {
-        match self.kind {
+        let exception = match self.kind {
+            // Re-raised as they are, so Ctrl-C and `exit` keep working during long conversions.
+            RutieException(ref exception) if is_interrupt(exception) => {
+                return rutie::AnyException::from(exception.value());
+            }
             RutieException(ref exception) => {
                 let msg = format!("{}{}", exception.message(), self.describe_context());
-                exception.exception(Some(&msg))
+                with_context(exception, &msg)
             }
             _ => {
                 let msg = format!("{}", self);
@@ -152,10 +307,201 @@ impl IntoException for Error {
                     default_class.new_instance(&[rutie::RString::new_utf8(&msg).to_any_object()]);
                 rutie::AnyException::from(obj.value())
             }
+        };
+        attach_backtrace(&exception, &self.backtrace);
+        attach_details(&exception, self.to_ruby_hash());
+        exception
+    }
+}
+
+// Exceptions which stop the program rather than report a failure: `Interrupt` (a
+// `SignalException`), other signals, and `exit`.
+const INTERRUPT_CLASSES: &[&str] = &["SignalException", "SystemExit"];
+
+pub(crate) fn is_interrupt(exception: &rutie::AnyException) -> bool {
+    INTERRUPT_CLASSES.iter().any(|class| {
+        exception
+            .protect_send(
+                "is_a?",
+                &[rutie::Class::from_existing(class).to_any_object()],
+            )
+            .map(|is_a| is_a.value().is_true())
+            .unwrap_or(false)
+    })
+}
+
+thread_local! {
+    static SET_RUST_BACKTRACE: OnceCell<rutie::AnyObject> = const { OnceCell::new() };
+    static WITH_CONTEXT: OnceCell<rutie::AnyObject> = const { OnceCell::new() };
+    static SET_DETAILS: OnceCell<rutie::AnyObject> = const { OnceCell::new() };
+}
+
+/// A copy of `exception` with the message `message`, keeping its class and Ruby backtrace, and
+/// with `exception` itself as its `cause`, so error trackers see where it was originally raised.
+fn with_context(exception: &rutie::AnyException, message: &str) -> rutie::AnyException {
+    let copier = WITH_CONTEXT.with(|copier| -> Result<rutie::AnyObject> {
+        if let Some(copier) = copier.get() {
+            return Ok(copier.clone());
+        }
+        // `cause` can only be set by raising, which keeps a backtrace that's already set.
+        let lambda = shareable_lambda(
+            "->(exception, message) do
+                copy = exception.exception(message)
+                copy.set_backtrace(exception.backtrace) if exception.backtrace
+                begin
+                  raise copy, cause: exception
+                rescue Exception => raised
+                  raised
+                end
+            end",
+        )?;
+        Ok(copier.get_or_init(|| lambda).clone())
+    });
+    let copy = copier.and_then(|copier| {
+        Ok(copier.protect_send(
+            "call",
+            &[
+                exception.to_any_object(),
+                RString::new_utf8(message).to_any_object(),
+            ],
+        )?)
+    });
+    match copy {
+        Ok(copy) => rutie::AnyException::from(copy.value()),
+        Err(_) => exception.exception(Some(message)),
+    }
+}
+
+/// Exposes `backtrace`, if it was captured, as `#rust_backtrace` on `exception`: an Array of
+/// Strings, one per line, in the format of `std::backtrace::Backtrace`'s `Display`.
+pub(crate) fn attach_backtrace(exception: &rutie::AnyException, backtrace: &Backtrace) {
+    if backtrace.status() != BacktraceStatus::Captured {
+        return;
+    }
+    let lines: Array = backtrace
+        .to_string()
+        .lines()
+        .map(|line| RString::new_utf8(line).to_any_object())
+        .collect();
+    let setter = SET_RUST_BACKTRACE.with(|setter| -> Result<rutie::AnyObject> {
+        if let Some(setter) = setter.get() {
+            return Ok(setter.clone());
+        }
+        let lambda = shareable_lambda(
+            "->(exception, backtrace) do
+                exception.instance_variable_set(:@rust_backtrace, backtrace.freeze)
+                exception.singleton_class.send(:attr_reader, :rust_backtrace)
+            end",
+        )?;
+        Ok(setter.get_or_init(|| lambda).clone())
+    });
+    // The backtrace is a debugging aid, so failing to attach it mustn't hide the exception.
+    if let Ok(setter) = setter {
+        let _ = setter.protect_send("call", &[exception.to_any_object(), lines.to_any_object()]);
+    }
+}
+
+/// Exposes `details`, from `Error::to_ruby_hash`, as `#rutie_serde_details` on `exception`.
+fn attach_details(exception: &rutie::AnyException, details: Hash) {
+    let setter = SET_DETAILS.with(|setter| -> Result<rutie::AnyObject> {
+        if let Some(setter) = setter.get() {
+            return Ok(setter.clone());
+        }
+        let lambda = shareable_lambda(
+            "->(exception, details) do
+                exception.instance_variable_set(:@rutie_serde_details, details.freeze)
+                exception.singleton_class.send(:attr_reader, :rutie_serde_details)
+            end",
+        )?;
+        Ok(setter.get_or_init(|| lambda).clone())
+    });
+    // Like the backtrace, the details are supplementary to the message.
+    if let Ok(setter) = setter {
+        let _ = setter.protect_send(
+            "call",
+            &[exception.to_any_object(), details.to_any_object()],
+        );
+    }
+}
+
+/// Maps the variants of an error type to Ruby exception classes.
+///
+/// Every `ExceptionMapping` is an `IntoException`, so such errors can be returned from
+/// `rutie_serde_methods!` bodies and are raised as the class chosen for their variant:
+///
+/// ```ignore
+/// impl ExceptionMapping for MyError {
+///     fn exception_class(&self) -> Option<&str> {
+///         match self {
+///             MyError::NotFound { .. } => Some("MyGem::NotFoundError"),
+///             MyError::Other(_) => None,
+///         }
+///     }
+///
+///     fn exception_data(&self) -> Option<AnyObject> {
+///         match self {
+///             MyError::NotFound { id } => new_ruby_object(id).ok(),
+///             MyError::Other(_) => None,
+///         }
+///     }
+/// }
+/// ```
+///
+/// The exception is constructed with the error's `Display` message, followed by its
+/// `exception_data` if there is any. If the class can't be found or refuses the arguments, the
+/// default exception class is raised instead, with the reason appended to the message.
+pub trait ExceptionMapping: fmt::Display {
+    /// The constant path of the exception class to raise, e.g. `"MyGem::NotFoundError"`, or
+    /// `None` for the default exception class.
+    fn exception_class(&self) -> Option<&str>;
+
+    /// Structured data passed to the exception's constructor as its second argument.
+    fn exception_data(&self) -> Option<rutie::AnyObject> {
+        None
+    }
+}
+
+impl<T> IntoException for T
+where
+    T: ExceptionMapping,
+{
+    fn into_exception(self, default_class: rutie::Class) -> rutie::AnyException {
+        let message = rutie::RString::new_utf8(&self.to_string()).to_any_object();
+        let mut arguments = vec![message];
+        arguments.extend(self.exception_data());
+        let class_path = match self.exception_class() {
+            Some(class_path) => class_path,
+            None => return new_exception(default_class, &arguments),
+        };
+        let mapped = rutie::Class::from_existing("Object")
+            .protect_send(
+                "const_get",
+                &[rutie::RString::new_utf8(class_path).to_any_object()],
+            )
+            .and_then(|class| class.protect_send("new", &arguments));
+        match mapped {
+            Ok(exception) => rutie::AnyException::from(exception.value()),
+            Err(error) => {
+                let msg = format!(
+                    "{}\n(Raising {} failed: {})",
+                    self,
+                    class_path,
+                    error.message()
+                );
+                new_exception(
+                    default_class,
+                    &[rutie::RString::new_utf8(&msg).to_any_object()],
+                )
+            }
         }
     }
 }
 
+fn new_exception(class: rutie::Class, arguments: &[rutie::AnyObject]) -> rutie::AnyException {
+    let obj = class.new_instance(arguments);
+    rutie::AnyException::from(obj.value())
+}
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 /// This extension trait allows callers to call `.chain_context` to add extra
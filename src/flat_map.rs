@@ -0,0 +1,132 @@
+use std::iter::FromIterator;
+
+use rutie::{AnyObject, Array, Boolean, Fixnum, Float, Hash, Object, RString, Symbol};
+
+use crate::de::probe;
+use crate::{Error, Result, ResultExt};
+
+/// A scalar value type which `flat_map_from_object`, `flat_map_to_object` and `from_object_bulk`
+/// convert directly, without going through the serde data model.
+pub trait FlatMapValue: Sized {
+    fn from_ruby_value(object: &AnyObject) -> Result<Self>;
+    fn to_ruby_value(&self) -> AnyObject;
+}
+
+impl FlatMapValue for String {
+    fn from_ruby_value(object: &AnyObject) -> Result<Self> {
+        Ok(object.try_convert_to::<RString>()?.to_string())
+    }
+
+    fn to_ruby_value(&self) -> AnyObject {
+        RString::new_utf8(self).to_any_object()
+    }
+}
+
+impl FlatMapValue for i64 {
+    fn from_ruby_value(object: &AnyObject) -> Result<Self> {
+        Ok(object.try_convert_to::<Fixnum>()?.to_i64())
+    }
+
+    fn to_ruby_value(&self) -> AnyObject {
+        Fixnum::new(*self).to_any_object()
+    }
+}
+
+impl FlatMapValue for f64 {
+    fn from_ruby_value(object: &AnyObject) -> Result<Self> {
+        Ok(Float::implicit_to_f(object.clone())?.to_f64())
+    }
+
+    fn to_ruby_value(&self) -> AnyObject {
+        Float::new(*self).to_any_object()
+    }
+}
+
+impl FlatMapValue for bool {
+    fn from_ruby_value(object: &AnyObject) -> Result<Self> {
+        Ok(object.try_convert_to::<Boolean>()?.to_bool())
+    }
+
+    fn to_ruby_value(&self) -> AnyObject {
+        Boolean::new(*self).to_any_object()
+    }
+}
+
+fn key_to_string(key: &AnyObject) -> Result<String> {
+    if let Some(symbol) = probe::<Symbol>(key) {
+        return Ok(symbol.to_string());
+    }
+    Ok(key.try_convert_to::<RString>()?.to_string())
+}
+
+/// Converts a flat Ruby Hash with String or Symbol keys, such as headers or labels, in a single
+/// pass over the hash.
+///
+/// This is considerably faster than `from_object` for large maps, as no serde machinery is
+/// involved per entry. The result can be any map type, e.g. `HashMap<String, String>` or
+/// `BTreeMap<String, i64>`.
+pub fn flat_map_from_object<M, V, O>(object: &O) -> Result<M>
+where
+    M: FromIterator<(String, V)>,
+    V: FlatMapValue,
+    O: Object,
+{
+    let hash = object.try_convert_to::<Hash>()?;
+    let mut entries = Vec::with_capacity(hash.length());
+    let mut error: Option<Error> = None;
+    hash.each(|key, value| {
+        if error.is_some() {
+            return;
+        }
+        let entry = key_to_string(&key).and_then(|key| {
+            V::from_ruby_value(&value)
+                .map(|value| (key.clone(), value))
+                .chain_context(|| format!("When converting the value of {:?}", key))
+        });
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(e) => error = Some(e),
+        }
+    });
+    match error {
+        Some(error) => Err(error.chain_context(|| "When converting a flat Hash")),
+        None => Ok(entries.into_iter().collect()),
+    }
+}
+
+/// The reverse of `flat_map_from_object`: builds a Ruby Hash with String keys from `map`.
+pub fn flat_map_to_object<'a, M, K, V>(map: M) -> AnyObject
+where
+    M: IntoIterator<Item = (&'a K, &'a V)>,
+    K: AsRef<str> + 'a,
+    V: FlatMapValue + 'a,
+{
+    let mut hash = Hash::new();
+    for (key, value) in map {
+        hash.store(RString::new_utf8(key.as_ref()), value.to_ruby_value());
+    }
+    hash.to_any_object()
+}
+
+/// Converts a Ruby Array of scalars, such as a `Vec<i64>` or `Vec<String>`, in a single pass over
+/// the array.
+///
+/// Like `flat_map_from_object`, this skips the serde machinery per element, which dominates the
+/// cost of converting arrays of tens of thousands of numbers or strings through `from_object`.
+/// All elements must be of the same kind; the first one which isn't is reported with its index.
+pub fn from_object_bulk<V, O>(object: &O) -> Result<Vec<V>>
+where
+    V: FlatMapValue,
+    O: Object,
+{
+    let array = object.try_convert_to::<Array>()?;
+    array
+        .into_iter()
+        .enumerate()
+        .map(|(index, element)| {
+            V::from_ruby_value(&element)
+                .chain_context(|| format!("When converting the element at index {}", index))
+        })
+        .collect::<Result<Vec<V>>>()
+        .chain_context(|| "When converting an Array in bulk")
+}
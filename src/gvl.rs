@@ -0,0 +1,155 @@
+//! Running Rust code without holding Ruby's Global VM Lock, so other Ruby threads can run
+//! meanwhile.
+//!
+//! Methods defined with `rutie_serde_methods!` can mark their body `gvl_free`, in which case the
+//! arguments are deserialized first, the body runs with the GVL released, and its result is
+//! serialized once the GVL is reacquired:
+//!
+//! ```ignore
+//! fn checksum(data: Vec<u8>) -> Result<u64, Error> gvl_free {
+//!     Ok(expensive_checksum(&data))
+//! }
+//! ```
+//!
+//! Ruby objects must not be touched while the GVL is released. In a `gvl_free` body the receiver
+//! is replaced by `GvlReleased`, and arguments must be `GvlFree`, i.e. plain Rust data, so they
+//! can't be used by accident; don't call into Ruby any other way (such as `Class::from_existing`
+//! or `VM::eval`) either. Argument types of your own implement `GvlFree` explicitly.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use rutie::Thread;
+use serde::de::DeserializeOwned;
+
+/// Stands in for the receiver of a method while its `gvl_free` body runs, as the receiver is a
+/// Ruby object which can't be used then.
+pub struct GvlReleased;
+
+/// Argument types of `gvl_free` methods: owned Rust data, deserialized before the GVL is released
+/// and safe to move to the code running without it.
+///
+/// It's implemented for primitives, `String`s, and `Option`s, `Box`es, tuples and the standard
+/// collections of `GvlFree` types. Other types opt in:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Upload {
+///     name: String,
+///     data: Vec<u8>,
+/// }
+///
+/// unsafe impl GvlFree for Upload {}
+/// ```
+///
+/// # Safety
+///
+/// The type must not give access to Ruby objects, through any of its fields: no rutie types such
+/// as `AnyObject`, none of this crate's handles such as `RutieObject`, `Raw`, `Wrapped`, `Value`,
+/// `RubyBlock` or `Splat`, and no fields deserialized `with::handle` or `with::passthrough`. Those
+/// are `Send`, but using them without the GVL is undefined behaviour.
+pub unsafe trait GvlFree: DeserializeOwned + Send {}
+
+macro_rules! gvl_free {
+    ($($ty:ty),* $(,)?) => {
+        $( unsafe impl GvlFree for $ty {} )*
+    };
+}
+
+gvl_free!(
+    (),
+    bool,
+    char,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64,
+    String,
+    PathBuf,
+    Duration,
+    SystemTime,
+);
+
+#[cfg(feature = "serde_json")]
+gvl_free!(serde_json::Value);
+
+unsafe impl<T: GvlFree> GvlFree for Option<T> {}
+unsafe impl<T: GvlFree> GvlFree for Box<T> {}
+unsafe impl<T: GvlFree> GvlFree for Vec<T> {}
+unsafe impl<T: GvlFree> GvlFree for VecDeque<T> {}
+unsafe impl<T: GvlFree + Ord> GvlFree for BTreeSet<T> {}
+unsafe impl<K: GvlFree + Ord, V: GvlFree> GvlFree for BTreeMap<K, V> {}
+unsafe impl<T, S> GvlFree for HashSet<T, S>
+where
+    T: GvlFree + Eq + Hash,
+    S: BuildHasher + Default + Send,
+{
+}
+unsafe impl<K, V, S> GvlFree for HashMap<K, V, S>
+where
+    K: GvlFree + Eq + Hash,
+    V: GvlFree,
+    S: BuildHasher + Default + Send,
+{
+}
+unsafe impl<T: GvlFree, E: GvlFree> GvlFree for Result<T, E> {}
+
+macro_rules! gvl_free_tuple {
+    ($($name:ident)+) => {
+        unsafe impl<$($name: GvlFree),+> GvlFree for ($($name,)+) {}
+    };
+}
+
+gvl_free_tuple!(A);
+gvl_free_tuple!(A B);
+gvl_free_tuple!(A B C);
+gvl_free_tuple!(A B C D);
+gvl_free_tuple!(A B C D E);
+gvl_free_tuple!(A B C D E F);
+gvl_free_tuple!(A B C D E F G);
+gvl_free_tuple!(A B C D E F G H);
+
+#[doc(hidden)]
+pub fn assert_gvl_free<T>()
+where
+    T: GvlFree,
+{
+}
+
+/// Calls `f` with the GVL released, and returns its result once the GVL has been reacquired.
+///
+/// `f` can't be interrupted: Ruby's `Thread#raise`, `Thread#kill` and signal handlers take effect
+/// after it returns. Panics in `f` are resumed after the GVL is reacquired, so `catch_and_raise`
+/// raises them as usual.
+pub fn without_gvl<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let mut f = Some(f);
+    // Unwinding out of the callback would cross Ruby's C frames.
+    let result = Thread::call_without_gvl(
+        move || {
+            let f = f.take().expect("without_gvl callback called twice");
+            panic::catch_unwind(AssertUnwindSafe(f))
+        },
+        None::<fn()>,
+    );
+    match result {
+        Ok(value) => value,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
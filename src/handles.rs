@@ -0,0 +1,80 @@
+//! A registry of Ruby objects behind stable integer handles.
+//!
+//! Handles are a GC-safe replacement for passing `object_id`s around and resolving them with
+//! `ObjectSpace._id2ref`, which is deprecated and returns garbage (or raises) if the object was
+//! collected in the meantime. A registered object is pinned, in the same way as with
+//! `pinned::Pinned`, until its handle is released, so the handle stays valid however long it
+//! travels through Rust, a queue or another serde format.
+//!
+//! ```ignore
+//! let handle = handles::register(&callback);
+//! // ... later, possibly after a round trip through JSON:
+//! let callback = handles::lookup(handle).ok_or("callback went away")?;
+//! handles::release(handle);
+//! ```
+//!
+//! `with::handle` wraps this up as a `#[serde(with = "...")]` module. Handles are process
+//! wide, but like any other Ruby object access, the functions here must only be called on a Ruby
+//! thread holding the GVL.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use rutie::rubysys::gc::{rb_gc_register_address, rb_gc_unregister_address};
+use rutie::types::{CallbackPtr, Value};
+use rutie::{AnyObject, Object};
+
+#[derive(Default)]
+struct Registry {
+    next_handle: u64,
+    // The registered slots must keep a stable address, hence the boxes.
+    objects: HashMap<u64, Box<Value>>,
+}
+
+static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn slot_address(slot: &Value) -> CallbackPtr {
+    slot as *const Value as CallbackPtr
+}
+
+/// Pins `object` and returns a new handle for it. Registering the same object twice returns two
+/// independent handles.
+pub fn register<T>(object: &T) -> u64
+where
+    T: Object,
+{
+    let slot = Box::new(object.value());
+    unsafe { rb_gc_register_address(slot_address(&slot)) };
+    let mut registry = registry();
+    registry.next_handle += 1;
+    let handle = registry.next_handle;
+    registry.objects.insert(handle, slot);
+    handle
+}
+
+/// Returns the object behind `handle`, or `None` if the handle was released or never existed.
+pub fn lookup(handle: u64) -> Option<AnyObject> {
+    registry()
+        .objects
+        .get(&handle)
+        .map(|slot| AnyObject::from(**slot))
+}
+
+/// Releases `handle`, unpinning its object, and returns the object if the handle was still live.
+pub fn release(handle: u64) -> Option<AnyObject> {
+    let slot = registry().objects.remove(&handle)?;
+    unsafe { rb_gc_unregister_address(slot_address(&slot)) };
+    Some(AnyObject::from(*slot))
+}
+
+/// Returns the number of live handles.
+pub fn len() -> usize {
+    registry().objects.len()
+}
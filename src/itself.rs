@@ -0,0 +1,79 @@
+use std::ops::Deref;
+
+use rutie::types::Value;
+use rutie::AnyObject;
+use serde::de::DeserializeOwned;
+
+use crate::{from_object_with_config, DeserializerConfig, MethodArgument, Result, ResultExt};
+
+extern "C" {
+    fn rb_current_receiver() -> Value;
+}
+
+/// The receiver of the current method, deserialized into `T`.
+///
+/// Declared as an argument of a `rutie_serde_methods!` or `#[rutie_serde::method]` method, it
+/// reads the receiver's state once per call, through its reader methods or instance variables
+/// as for any other object, and doesn't take up a positional argument:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Account {
+///     balance: i64,
+///     frozen: bool,
+/// }
+///
+/// rutie_serde_methods!(
+///     RubyAccount,
+///     _itself,
+///     ruby_class!(Exception),
+///     fn can_withdraw(account: ItselfAs<Account>, amount: i64) -> bool {
+///         match *account {
+///             Account { frozen: true, .. } => false,
+///             Account { balance, .. } => balance >= amount,
+///         }
+///     }
+/// );
+/// ```
+pub struct ItselfAs<T>(pub T);
+
+impl<T> ItselfAs<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ItselfAs<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'a, T> MethodArgument<'a> for ItselfAs<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_method_arguments(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+    ) -> Option<Result<Self>> {
+        Self::from_method_arguments_with_config(arguments, position, &DeserializerConfig::default())
+    }
+
+    fn from_method_arguments_with_config(
+        _arguments: &'a [AnyObject],
+        _position: &mut usize,
+        config: &DeserializerConfig,
+    ) -> Option<Result<Self>> {
+        let receiver = AnyObject::from(unsafe { rb_current_receiver() });
+        let itself = from_object_with_config(&receiver, config.clone())
+            .chain_context(|| "When deserializing the receiver");
+        Some(itself.map(ItselfAs))
+    }
+
+    fn positional_arguments() -> Option<usize> {
+        Some(0)
+    }
+}
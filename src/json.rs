@@ -0,0 +1,30 @@
+use rutie::{AnyObject, Object};
+
+use crate::{transcode_from_ruby, transcode_to_ruby, Error, Result, ResultExt};
+
+/// Converts a Ruby object made of core types, such as a Hash argument, straight into JSON.
+///
+/// The data goes from `rutie_serde`'s `Deserializer` into `serde_json`'s serializer without any
+/// intermediate Rust value. Symbols become strings, and Hash keys must be Strings or Symbols.
+pub fn to_json_string<O>(object: &O) -> Result<String>
+where
+    O: Object,
+{
+    let mut json = Vec::new();
+    transcode_from_ruby(object, &mut serde_json::Serializer::new(&mut json))
+        .chain_context(|| "When converting to JSON")?;
+    String::from_utf8(json).map_err(|error| Error::from(error.to_string()))
+}
+
+/// The reverse of `to_json_string`: builds the equivalent Ruby object, with String Hash keys,
+/// from the JSON document `json`.
+pub fn from_json_str_to_ruby(json: &str) -> Result<AnyObject> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let object =
+        transcode_to_ruby(&mut deserializer).chain_context(|| "When converting from JSON")?;
+    deserializer
+        .end()
+        .map_err(|error| Error::from(error.to_string()))
+        .chain_context(|| "When converting from JSON")?;
+    Ok(object)
+}
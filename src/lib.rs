@@ -2,19 +2,88 @@
 #[macro_use]
 mod macros;
 
+mod arity;
+#[cfg(feature = "rust_decimal")]
+pub mod bigdecimal_serde;
+mod block;
+mod cache;
+mod class_map;
+pub mod codegen;
+mod constants;
+mod convert;
 mod de;
 mod error;
+mod flat_map;
+pub mod gvl;
+pub mod handles;
+mod itself;
+#[cfg(feature = "serde_json")]
+mod json;
+mod lossy;
+mod or_unknown;
 pub mod panics;
+pub mod pinned;
+pub mod ractor;
+mod range;
+mod raw;
+mod raw_object;
+mod rename;
+pub mod ruby_errors;
+mod ruby_object;
 mod ser;
+mod shared;
+mod splat;
+#[cfg(feature = "chrono")]
+pub mod time_serde;
+mod transcode;
+mod value;
+pub mod warnings;
+pub mod with;
+mod with_source;
+mod wrapped;
 
+pub use self::arity::*;
+pub use self::block::*;
+pub use self::class_map::*;
+pub use self::constants::*;
+pub use self::convert::*;
 pub use self::de::*;
 pub use self::error::*;
+pub use self::flat_map::*;
+pub use self::itself::*;
+#[cfg(feature = "serde_json")]
+pub use self::json::*;
+pub use self::lossy::*;
+pub use self::or_unknown::*;
+pub use self::range::*;
+pub use self::raw_object::*;
+pub use self::rename::*;
+pub use self::ruby_object::*;
 pub use self::ser::*;
+pub use self::shared::*;
+pub use self::splat::*;
+pub use self::transcode::*;
+pub use self::value::*;
+pub use self::with_source::*;
+pub use self::wrapped::*;
+
+pub use rutie_serde_macros::{method, FromRubyObject};
 
 use rutie::{AnyObject, Object};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// A wrapper for `rutie::AnyObject` to allow it to be used in `rutie_serde` function signatures.
+///
+/// `RutieObject` also implements `Serialize` and `Deserialize`, so it can be used inside
+/// serde-powered types, e.g. as `HashMap<String, RutieObject>` values or as the value type of a
+/// `#[serde(flatten)]` catch-all map. With `rutie_serde`'s own `Serializer` and `Deserializer` the
+/// Ruby object passes through untouched. Other formats (and serde's internal buffering, which
+/// `#[serde(flatten)]` relies on) see the equivalent plain data instead, from which an equivalent
+/// Ruby object is rebuilt on deserialization. Serializing to them converts the object as
+/// `Value::from_object` does, so it fails for objects other than core types.
+///
+/// A `RutieObject` doesn't keep its object alive or in place. If it's stored on the heap beyond
+/// the current method call, hold a `pinned::Pinned` for the object as well.
 #[repr(C)]
 pub struct RutieObject(pub AnyObject);
 
@@ -27,13 +96,51 @@ where
     }
 }
 
+impl Serialize for RutieObject {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        raw::serialize_or(&self.0, &PlainData(&self.0), serializer)
+    }
+}
+
+// Converts the object into a `Value` only when serialized, so that `rutie_serde`'s `Serializer`
+// doesn't pay for it.
+struct PlainData<'a>(&'a AnyObject);
+
+impl<'a> Serialize for PlainData<'a> {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Value::from_object(self.0)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RutieObject {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_newtype_struct(raw::RAW_OBJECT_TOKEN, transcode::RubyObjectVisitor)
+            .map(RutieObject)
+    }
+}
+
 pub trait IntoAnyObject {
     fn into_any_object(self) -> Result<AnyObject>;
-}
 
-impl IntoAnyObject for RutieObject {
-    fn into_any_object(self) -> Result<AnyObject> {
-        Ok(self.0)
+    /// Like `into_any_object`, for methods declared with `#[options(serialize = ...)]`. By
+    /// default the config is ignored.
+    fn into_any_object_with_config(self, _config: SerializerConfig) -> Result<AnyObject>
+    where
+        Self: Sized,
+    {
+        self.into_any_object()
     }
 }
 
@@ -44,6 +151,102 @@ where
     fn into_any_object(self) -> Result<AnyObject> {
         new_ruby_object(self)
     }
+
+    fn into_any_object_with_config(self, config: SerializerConfig) -> Result<AnyObject> {
+        new_ruby_object_with_config(self, config)
+    }
+}
+
+/// Method-call syntax for `new_ruby_object`: `user.to_ruby()?`.
+///
+/// A type which also implements `RubyConvert` has two `to_ruby` methods when both traits are in
+/// scope; call `ToRuby::to_ruby(&value)` or `RubyConvert::to_ruby(&value)` to pick one.
+pub trait ToRuby {
+    fn to_ruby(&self) -> Result<AnyObject>;
+}
+
+impl<T> ToRuby for T
+where
+    T: Serialize + ?Sized,
+{
+    fn to_ruby(&self) -> Result<AnyObject> {
+        new_ruby_object(self)
+    }
+}
+
+/// Method-call syntax for `from_object`: `object.from_ruby::<User>()?`.
+pub trait FromRuby {
+    #[allow(clippy::wrong_self_convention)]
+    fn from_ruby<T>(&self) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned;
+}
+
+impl<O> FromRuby for O
+where
+    O: Object,
+{
+    fn from_ruby<T>(&self) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        from_object(self)
+    }
+}
+
+/// How `rutie_serde_methods!` obtains the value of each method argument from the arguments the
+/// method was called with.
+///
+/// Arguments are deserialized from consecutive positional arguments using `from_object`. Other
+/// implementations, such as `RubyBlock`, can read from elsewhere and take up no positional
+/// arguments.
+pub trait MethodArgument<'a>: Sized {
+    /// Reads the argument starting at `arguments[*position]`, advancing `position` past the
+    /// positional arguments used. Returns `None` if an argument is missing.
+    fn from_method_arguments(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+    ) -> Option<Result<Self>>;
+
+    /// Like `from_method_arguments`, for methods declared with `#[options(deserialize = ...)]`.
+    /// Arguments which aren't deserialized ignore the config.
+    fn from_method_arguments_with_config(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+        _config: &DeserializerConfig,
+    ) -> Option<Result<Self>> {
+        Self::from_method_arguments(arguments, position)
+    }
+
+    /// The number of positional arguments this argument takes up, for arity checks. `None`
+    /// means any number.
+    fn positional_arguments() -> Option<usize> {
+        Some(1)
+    }
+}
+
+impl<'a, O> MethodArgument<'a> for O
+where
+    O: Deserialize<'a>,
+{
+    fn from_method_arguments(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+    ) -> Option<Result<Self>> {
+        let argument = arguments.get(*position)?;
+        *position += 1;
+        Some(DeserializeWrapper::deserialize(argument))
+    }
+
+    fn from_method_arguments_with_config(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+        config: &DeserializerConfig,
+    ) -> Option<Result<Self>> {
+        let argument = arguments.get(*position)?;
+        *position += 1;
+        Some(from_object_with_config(argument, config.clone()))
+    }
 }
 
 /// Abstraction around deserialization from T: Object -> O: Deserialize
@@ -54,12 +257,6 @@ pub trait DeserializeWrapper<T> {
         Self: Sized;
 }
 
-impl<'a> DeserializeWrapper<&'a AnyObject> for RutieObject {
-    fn deserialize(data: &'a AnyObject) -> Result<RutieObject> {
-        Ok(RutieObject(data.clone()))
-    }
-}
-
 impl<'a, T, O> DeserializeWrapper<&'a T> for O
 where
     O: Deserialize<'a>,
@@ -70,10 +267,28 @@ where
     }
 }
 
+/// `#[serde(with = "rutie_serde::anyobject_serde")]` for `AnyObject` fields, exchanged as the
+/// object's `object_id`.
+///
+/// The object must be kept alive by the caller until it is deserialized again, and
+/// `ObjectSpace._id2ref` is deprecated in newer Rubies. Prefer `with::handle`, which pins the
+/// object behind a `handles` handle instead.
 pub mod anyobject_serde {
     use rutie::{AnyObject, Class, Fixnum, Object};
     use serde::de::Error;
-    use serde::{Deserialize, Deserializer};
+    use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(object: &AnyObject, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let object_id = object
+            .protect_public_send("object_id", &[])
+            .map_err(|_e| ser::Error::custom("object_id raised an error"))?
+            .try_convert_to::<Fixnum>()
+            .map_err(|_e| ser::Error::custom("object_id is not a Fixnum"))?;
+        (object_id.to_i64() as usize).serialize(serializer)
+    }
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<AnyObject, D::Error>
     where
@@ -0,0 +1,68 @@
+use std::any;
+use std::ops::{Deref, DerefMut};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::warnings::push_warning;
+
+/// A wrapper whose deserialization never fails: if `T` can't be deserialized, `T::default()` is
+/// used instead and the failure is recorded with `warnings::push_warning`.
+///
+/// This is meant for ingestion pipelines which must not reject a whole payload because a single
+/// field is malformed. Serialization is transparent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lossy<T>(pub T);
+
+impl<T> Lossy<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Lossy<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Lossy<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Lossy<T>
+where
+    T: Deserialize<'de> + Default,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match T::deserialize(deserializer) {
+            Ok(value) => Ok(Lossy(value)),
+            Err(error) => {
+                push_warning(format!(
+                    "Using the default {} after failing to deserialize: {}",
+                    any::type_name::<T>(),
+                    error
+                ));
+                Ok(Lossy(T::default()))
+            }
+        }
+    }
+}
+
+impl<T> Serialize for Lossy<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
@@ -7,11 +7,40 @@ macro_rules! ruby_class {
     )
 }
 
+/// Like `ruby_class!`, but defines the class (and any modules it is nested in) if it doesn't
+/// exist yet, optionally with a superclass:
+///
+/// ```ignore
+/// let error = ruby_class_or_define!(MyGem::Error < StandardError);
+/// let user = ruby_class_or_define!(MyGem::Models::User);
+/// ```
+///
+/// Panics if the constant exists but isn't a subclass of the superclass; use `class_or_define`
+/// to handle this as an error instead.
+#[macro_export]
+macro_rules! ruby_class_or_define {
+    ($outer_class:ident$(::$inner_class:ident)* < $super_outer:ident$(::$super_inner:ident)*) => (
+        $crate::__ruby_class_or_define(
+            concat!(stringify!($outer_class) $(, "::", stringify!($inner_class))*),
+            Some(&$crate::ruby_class!($super_outer$(::$super_inner)*)),
+        )
+    );
+    ($outer_class:ident$(::$inner_class:ident)*) => (
+        $crate::__ruby_class_or_define(
+            concat!(stringify!($outer_class) $(, "::", stringify!($inner_class))*),
+            None,
+        )
+    );
+}
+
 /// A macro to define Rutie methods, which uses `rutie_serde` to deserialize arguments and serialize results.
 ///
 /// In comparison to `rutie::methods!`, this macro:
 ///
 ///  - Attempts to use `rutie_serde` to deserialize into the required type for each argument.
+///    Trailing arguments can be made optional by giving them a default value, as in
+///    `fn find(id: u32, scope: Option<String> = None)`. A final `Splat` argument collects any
+///    remaining arguments, and an argument of type `RubyBlock` receives the method's block.
 ///  - Allows methods to return either `Result<T, E>` or `T`, where `T: IntoAnyObject` and
 ///    `E: IntoException`. Errors are safely raised as Ruby exceptions and successful computations
 ///    are serialized into Ruby objects using `rutie_serde`.
@@ -19,11 +48,68 @@ macro_rules! ruby_class {
 ///    them as a Ruby exception.
 ///  - Catches any errors that occur during `rutie_serde` deserialization/serialization and safely
 ///    raises them as Ruby exceptions.
+///  - Raises an `ArgumentError` if the method is called with the wrong number of arguments,
+///    unless `set_lenient_arity` was used to turn this off.
+///
+/// It accepts an optional `exception_class` argument after the receiver's name, an expression
+/// resulting in a `rutie::Class` which is used to instantiate exceptions that are raised from
+/// panics and errors. Without it, each kind of failure is raised as its
+/// `ruby_errors::default_class`: a `DeserializationError` for arguments which can't be
+/// deserialized, and so on, or a `StandardError` unless `ruby_errors::init` was called.
 ///
-/// It accepts an extra `exception_class` argument, which should be an expression resulting in a
-/// `rutie::Class` which is used to instantiate exceptions that are raised from panics.
+/// A method can choose its own deserialization and serialization options with an `#[options]`
+/// attribute, taking anything which converts into a `DeserializerConfig` or `SerializerConfig`,
+/// respectively:
+///
+/// ```ignore
+/// #[options(deserialize = BoolCoercion::Strings, serialize = KeyStyle::String)]
+/// fn settings(overrides: Settings) -> Settings { ... }
+/// ```
+///
+/// Methods returning a `Result` can be marked `gvl_free` after the return type, as in
+/// `fn digest(data: Vec<u8>) -> Result<String, Error> gvl_free { ... }`, to run their body with
+/// Ruby's GVL released; see the `gvl` module.
+///
+/// Methods declared as `self fn` are meant to be registered with `def_self` or as module
+/// functions: their receiver is the class or module itself, exposed to the body as a
+/// `rutie::AnyObject` instead of an `$itself_class`.
 #[macro_export]
 macro_rules! rutie_serde_methods {
+    // Without an exception class, raise the `ruby_errors` class of each kind of failure.
+    (
+        $itself_class:ty,
+        $itself_name:ident,
+
+        $(#[options($($options:tt)*)])?
+        fn $($methods:tt)*
+    ) => {
+        $crate::rutie_serde_methods!(
+            $itself_class,
+            $itself_name,
+            $crate::ruby_errors::DefaultClasses,
+
+            $(#[options($($options)*)])?
+            fn $($methods)*
+        );
+    };
+
+    (
+        $itself_class:ty,
+        $itself_name:ident,
+
+        $(#[options($($options:tt)*)])?
+        self fn $($methods:tt)*
+    ) => {
+        $crate::rutie_serde_methods!(
+            $itself_class,
+            $itself_name,
+            $crate::ruby_errors::DefaultClasses,
+
+            $(#[options($($options)*)])?
+            self fn $($methods)*
+        );
+    };
+
     // This macro is recursive and defines one method each time it recurses. This is the base-case
     // where there are no more methods to define.
     (
@@ -32,13 +118,139 @@ macro_rules! rutie_serde_methods {
         $exception_class:expr,
     ) => {};
 
+    // Define a `gvl_free` method by wrapping its body in `gvl::without_gvl` and recursing. The
+    // receiver is shadowed inside the body, and arguments must be `GvlFree`.
+    (
+        $itself_class:ty,
+        $itself_name:ident,
+        $exception_class:expr,
+
+        $(#[options($($options:tt)*)])?
+        fn $method_name:ident($($arg_name:ident: $arg_type:ty $(= $default:expr)?),* $(,)*) -> Result<$return_type:ty, $error_type:ty>
+        gvl_free $body:block
+
+        $($other_methods:tt)*
+    ) => {
+        $crate::rutie_serde_methods!(
+            $itself_class,
+            $itself_name,
+            $exception_class,
+
+            $(#[options($($options)*)])?
+            fn $method_name($($arg_name: $arg_type $(= $default)?),*) -> Result<$return_type, $error_type>
+            {
+                $( $crate::gvl::assert_gvl_free::<$arg_type>(); )*
+                $crate::gvl::without_gvl(move || -> Result<$return_type, $error_type> {
+                    #[allow(unused_variables)]
+                    let $itself_name = $crate::gvl::GvlReleased;
+                    $body
+                })
+            }
+
+            $($other_methods)*
+        );
+    };
+
+    (
+        $itself_class:ty,
+        $itself_name:ident,
+        $exception_class:expr,
+
+        $(#[options($($options:tt)*)])?
+        self fn $method_name:ident($($args:tt)*) -> Result<$return_type:ty, $error_type:ty>
+        gvl_free $body:block
+
+        $($other_methods:tt)*
+    ) => {
+        $crate::rutie_serde_methods!(
+            ::rutie::AnyObject,
+            $itself_name,
+            $exception_class,
+
+            $(#[options($($options)*)])?
+            fn $method_name($($args)*) -> Result<$return_type, $error_type>
+            gvl_free $body
+        );
+
+        $crate::rutie_serde_methods!(
+            $itself_class,
+            $itself_name,
+            $exception_class,
+
+            $($other_methods)*
+        );
+    };
+
+    // Define a `self fn`, whose receiver is a class or module rather than an instance, by
+    // defining it as a method on `AnyObject` and recursing with the original receiver type.
+    (
+        $itself_class:ty,
+        $itself_name:ident,
+        $exception_class:expr,
+
+        $(#[options($($options:tt)*)])?
+        self fn $method_name:ident($($args:tt)*) -> Result<$return_type:ty, $error_type:ty>
+        $body:block
+
+        $($other_methods:tt)*
+    ) => {
+        $crate::rutie_serde_methods!(
+            ::rutie::AnyObject,
+            $itself_name,
+            $exception_class,
+
+            $(#[options($($options)*)])?
+            fn $method_name($($args)*) -> Result<$return_type, $error_type>
+            $body
+        );
+
+        $crate::rutie_serde_methods!(
+            $itself_class,
+            $itself_name,
+            $exception_class,
+
+            $($other_methods)*
+        );
+    };
+
+    (
+        $itself_class:ty,
+        $itself_name:ident,
+        $exception_class:expr,
+
+        $(#[options($($options:tt)*)])?
+        self fn $method_name:ident($($args:tt)*) -> $return_type:ty
+        $body:block
+
+        $($other_methods:tt)*
+    ) => {
+        $crate::rutie_serde_methods!(
+            ::rutie::AnyObject,
+            $itself_name,
+            $exception_class,
+
+            $(#[options($($options)*)])?
+            fn $method_name($($args)*) -> $return_type
+            $body
+        );
+
+        $crate::rutie_serde_methods!(
+            $itself_class,
+            $itself_name,
+            $exception_class,
+
+            $($other_methods)*
+        );
+    };
+
     // Define a method that returns a `Result<T, E>` where `T: IntoAnyObject, E: IntoException`.
     (
         $itself_class:ty,
         $itself_name:ident,
         $exception_class:expr,
 
-        fn $method_name:ident($($arg_name:ident: $arg_type:ty),* $(,)*) -> Result<$return_type:ty, $error_type:ty>
+        $(#[options($($options:tt)*)])?
+        fn $method_name:ident($($arg_name:ident: $arg_type:ty $(= $default:expr)?),* $(,)*) -> Result<$return_type:ty, $error_type:ty>
         $body:block
 
         $($other_methods:tt)*
@@ -52,54 +264,77 @@ macro_rules! rutie_serde_methods {
             // letting Rust cleanup first.
             use ::std::result::Result;
             use rutie;
-            use $crate::{self, DeserializeWrapper, IntoAnyObject, IntoException, ResultExt};
+            use $crate::{IntoAnyObject, IntoException, MethodArgument, ResultExt};
             use $crate::panics::catch_and_raise;
 
+            use $crate::ruby_errors::{ErrorClass, ExceptionClasses};
+
             enum ClosureError {
-                RutieSerde($crate::Error),
+                Deserialization($crate::Error),
+                Serialization($crate::Error),
                 Body($error_type),
             }
 
-            impl IntoException for ClosureError {
-                fn into_exception(self, default_class: rutie::Class) -> rutie::AnyException {
+            impl ClosureError {
+                fn kind(&self) -> ErrorClass {
                     match self {
-                        ClosureError::RutieSerde(error) => IntoException::into_exception(error, default_class),
-                        ClosureError::Body(error) => IntoException::into_exception(error, default_class),
+                        ClosureError::Deserialization(_) => ErrorClass::Deserialization,
+                        ClosureError::Serialization(_) => ErrorClass::Serialization,
+                        ClosureError::Body(_) => ErrorClass::Error,
                     }
                 }
             }
 
-            impl From<$crate::Error> for ClosureError {
-                fn from(error: $crate::Error) -> ClosureError {
-                    ClosureError::RutieSerde(error)
+            impl IntoException for ClosureError {
+                fn into_exception(self, default_class: rutie::Class) -> rutie::AnyException {
+                    match self {
+                        ClosureError::Deserialization(error) | ClosureError::Serialization(error) => {
+                            IntoException::into_exception(error, default_class)
+                        }
+                        ClosureError::Body(error) => IntoException::into_exception(error, default_class),
+                    }
                 }
             }
 
-            let result = catch_and_raise($exception_class, move || -> Result<rutie::AnyObject, ClosureError> {
+            let panic_class = ExceptionClasses::class(&$exception_class, ErrorClass::Panic);
+            let result = catch_and_raise(panic_class, move || -> Result<rutie::AnyObject, ClosureError> {
                 let _arguments = rutie::util::parse_arguments(argc, argv);
+                let _deserializer_config: $crate::DeserializerConfig =
+                    $crate::__rutie_serde_options!(@deserialize $($($options)*)?);
+                let _serializer_config: $crate::SerializerConfig =
+                    $crate::__rutie_serde_options!(@serialize $($($options)*)?);
+                #[allow(unused_mut)]
+                let mut _arity = $crate::Arity::default();
+                $(
+                    _arity = _arity.with_argument(
+                        <$arg_type as MethodArgument>::positional_arguments(),
+                        $crate::__rutie_serde_has_default!($($default)?),
+                    );
+                )*
+                _arity.check(_arguments.len()).map_err(ClosureError::Deserialization)?;
+
                 #[allow(unused_mut)]
                 let mut _i = 0;
 
                 $(
                     let $arg_name: $arg_type =
-                        _arguments
-                            .get(_i)
-                            .ok_or_else(|| {
-                                let err: rutie_serde::Error =
+                        <$arg_type as MethodArgument>::from_method_arguments_with_config(
+                            &_arguments,
+                            &mut _i,
+                            &_deserializer_config,
+                        )
+                            .unwrap_or_else(|| {
+                                let err: $crate::Error =
                                     format!(
                                         "Argument '{}: {}' not found for method '{}'",
                                         stringify!($arg_name),
                                         stringify!($arg_type),
                                         stringify!($method_name)
                                     ).into();
-                                err
+                                $crate::__rutie_serde_missing_argument!(err; $($default)?)
                             })
-                            .map_err($crate::Error::from)
-                            .and_then(|object| DeserializeWrapper::deserialize(object))
                             .chain_context(|| format!("When deserializing arg: {}", stringify!($arg_name)))
-                            .map_err(ClosureError::RutieSerde)?;
-
-                    _i += 1;
+                            .map_err(ClosureError::Deserialization)?;
                 )*
 
                 #[allow(unused_variables)]
@@ -109,15 +344,16 @@ macro_rules! rutie_serde_methods {
                 result
                     .map_err(ClosureError::Body)
                     .and_then(|return_value| {
-                        IntoAnyObject::into_any_object(return_value)
-                            .map_err(ClosureError::RutieSerde)
+                        IntoAnyObject::into_any_object_with_config(return_value, _serializer_config)
+                            .map_err(ClosureError::Serialization)
                     })
             });
 
             match result {
                 Ok(value) => value,
                 Err(error) => {
-                    let exception = error.into_exception($exception_class);
+                    let class = ExceptionClasses::class(&$exception_class, error.kind());
+                    let exception = error.into_exception(class);
                     rutie::VM::raise_ex(exception);
                     unreachable!("::rutie::VM::raise_ex")
                 }
@@ -125,7 +361,7 @@ macro_rules! rutie_serde_methods {
         }
 
         // Recurse and define the rest of the methods.
-        rutie_serde_methods!(
+        $crate::rutie_serde_methods!(
             $itself_class,
             $itself_name,
             $exception_class,
@@ -142,17 +378,19 @@ macro_rules! rutie_serde_methods {
         $itself_name:ident,
         $exception_class:expr,
 
-        fn $method_name:ident($($arg_name:ident: $arg_type:ty),* $(,)*) -> $return_type:ty
+        $(#[options($($options:tt)*)])?
+        fn $method_name:ident($($arg_name:ident: $arg_type:ty $(= $default:expr)?),* $(,)*) -> $return_type:ty
         $body:block
 
         $($other_methods:tt)*
     ) => {
-        rutie_serde_methods!(
+        $crate::rutie_serde_methods!(
             $itself_class,
             $itself_name,
             $exception_class,
 
-            fn $method_name($($arg_name:$arg_type),*)
+            $(#[options($($options)*)])?
+            fn $method_name($($arg_name: $arg_type $(= $default)?),*)
                 -> Result<$return_type, $crate::Error>
             {
                 let return_value = $body;
@@ -165,3 +403,222 @@ macro_rules! rutie_serde_methods {
         );
     };
 }
+
+// The value of an argument which the caller left out: its default value if the method declares
+// one, and otherwise the "not found" error.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rutie_serde_missing_argument {
+    ($error:expr; $default:expr) => {
+        Ok($default)
+    };
+    ($error:expr;) => {
+        Err($error)
+    };
+}
+
+// The configs given by a method's `#[options(deserialize = ..., serialize = ...)]`, which can be
+// anything converting into a `DeserializerConfig` or `SerializerConfig` respectively.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rutie_serde_options {
+    (@$target:ident) => {
+        ::std::default::Default::default()
+    };
+    (@deserialize deserialize = $config:expr $(, $($rest:tt)*)?) => {
+        ::std::convert::Into::<$crate::DeserializerConfig>::into($config)
+    };
+    (@serialize serialize = $config:expr $(, $($rest:tt)*)?) => {
+        ::std::convert::Into::<$crate::SerializerConfig>::into($config)
+    };
+    (@$target:ident deserialize = $config:expr $(, $($rest:tt)*)?) => {
+        $crate::__rutie_serde_options!(@$target $($($rest)*)?)
+    };
+    (@$target:ident serialize = $config:expr $(, $($rest:tt)*)?) => {
+        $crate::__rutie_serde_options!(@$target $($($rest)*)?)
+    };
+    (@$target:ident $($unknown:tt)*) => {
+        compile_error!(concat!(
+            "unknown method option `",
+            stringify!($($unknown)*),
+            "`, expected `deserialize = ...` or `serialize = ...`"
+        ))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rutie_serde_has_default {
+    ($default:expr) => {
+        true
+    };
+    () => {
+        false
+    };
+}
+
+/// A macro to define `rutie_serde` methods on a single Ruby object rather than on a class, in the
+/// same way as `define_singleton_method`.
+///
+/// This is useful for attaching native behaviour to module objects or configuration singletons.
+/// Methods are declared exactly as in `rutie_serde_methods!` and get the same argument
+/// deserialization, result serialization, panic catching and exception raising. The receiver is
+/// exposed to each body as a `rutie::AnyObject`.
+///
+/// ```ignore
+/// let mut config = Module::from_existing("Config");
+/// rutie_serde_singleton_methods!(
+///     config,
+///     _itself,
+///     ruby_class!(Exception),
+///     fn lookup(key: String) -> Option<String> {
+///         std::env::var(key).ok()
+///     }
+/// );
+/// ```
+#[macro_export]
+macro_rules! rutie_serde_singleton_methods {
+    // Walk the method declarations and register every `fn $method_name` found at the top level.
+    // Argument lists and bodies are single token trees, so only method names are matched here.
+    (@define $object:ident;) => {};
+
+    (@define $object:ident; fn $method_name:ident $($rest:tt)*) => {
+        ::rutie::Object::define_singleton_method(&mut *$object, stringify!($method_name), $method_name);
+        $crate::rutie_serde_singleton_methods!(@define $object; $($rest)*);
+    };
+
+    (@define $object:ident; $_skip:tt $($rest:tt)*) => {
+        $crate::rutie_serde_singleton_methods!(@define $object; $($rest)*);
+    };
+
+    (
+        $object:expr,
+        $itself_name:ident,
+        $exception_class:expr,
+
+        $($methods:tt)*
+    ) => {{
+        $crate::rutie_serde_methods!(
+            ::rutie::AnyObject,
+            $itself_name,
+            $exception_class,
+
+            $($methods)*
+        );
+
+        let object = &mut $object;
+        $crate::rutie_serde_singleton_methods!(@define object; $($methods)*);
+    }};
+}
+
+/// A macro to declare a Ruby class or module together with its `rutie_serde` methods.
+///
+/// Methods are declared exactly as in `rutie_serde_methods!`, under `class_methods` (registered
+/// with `def_self`) and `instance_methods` (registered with `def`). Both sections are optional.
+/// The macro generates a module named after the Ruby class, holding the methods and a `define`
+/// function which creates (or reopens) the class or module and registers the methods on it:
+///
+/// ```ignore
+/// rutie_serde_class!(
+///     class RubyRustDemo,
+///     _itself,
+///     ruby_class!(Exception),
+///     class_methods {
+///         fn hello(name: String) -> String {
+///             format!("Hello {}", name)
+///         }
+///     }
+/// );
+///
+/// #[no_mangle]
+/// pub extern "C" fn Init_ruby_rust_demo() {
+///     RubyRustDemo::define();
+/// }
+/// ```
+///
+/// Use `module Name` instead of `class Name` to declare a module. The receiver is exposed to each
+/// body as a `rutie::AnyObject`.
+#[macro_export]
+macro_rules! rutie_serde_class {
+    // Walk the method declarations and register every `fn $method_name` found at the top level,
+    // as in `rutie_serde_singleton_methods!`.
+    (@define $target:ident, $def:ident;) => {};
+
+    (@define $target:ident, $def:ident; fn $method_name:ident $($rest:tt)*) => {
+        ::rutie::Object::$def(&mut $target, stringify!($method_name), $method_name);
+        $crate::rutie_serde_class!(@define $target, $def; $($rest)*);
+    };
+
+    (@define $target:ident, $def:ident; $_skip:tt $($rest:tt)*) => {
+        $crate::rutie_serde_class!(@define $target, $def; $($rest)*);
+    };
+
+    (@methods $itself_name:ident, $exception_class:expr, $($methods:tt)*) => {
+        $crate::rutie_serde_methods!(
+            ::rutie::AnyObject,
+            $itself_name,
+            $exception_class,
+
+            $($methods)*
+        );
+    };
+
+    // Reopening a class keeps its superclass, whatever it is.
+    (@new class $name:ident) => {
+        $crate::__class_or_define_or_raise(stringify!($name))
+    };
+
+    (@new module $name:ident) => {
+        ::rutie::Module::new(stringify!($name))
+    };
+
+    (
+        $kind:ident $name:ident,
+        $itself_name:ident,
+        $exception_class:expr,
+
+        $(class_methods { $($class_methods:tt)* })?
+        $(instance_methods { $($instance_methods:tt)* })?
+    ) => {
+        #[allow(non_snake_case)]
+        pub mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $($crate::rutie_serde_class!(@methods $itself_name, $exception_class, $($class_methods)*);)?
+            $($crate::rutie_serde_class!(@methods $itself_name, $exception_class, $($instance_methods)*);)?
+
+            /// Defines the Ruby class or module, or reopens it if it exists, and registers its
+            /// methods.
+            #[allow(unused_mut)]
+            pub fn define() -> ::rutie::AnyObject {
+                let mut target = $crate::rutie_serde_class!(@new $kind $name);
+                $($crate::rutie_serde_class!(@define target, def_self; $($class_methods)*);)?
+                $($crate::rutie_serde_class!(@define target, def; $($instance_methods)*);)?
+                ::rutie::Object::to_any_object(&target)
+            }
+        }
+    };
+}
+
+/// Exports Rust constants as Ruby constants on a module or class, using the `Serialize`
+/// representation of each value. Evaluates to a `rutie_serde::Result<()>`.
+///
+/// ```ignore
+/// const MAX_RETRIES: u32 = 3;
+/// const DEFAULT_REGION: &str = "eu-west-1";
+///
+/// rutie_serde_constants!(module, MAX_RETRIES, DEFAULT_REGION)?;
+/// ```
+#[macro_export]
+macro_rules! rutie_serde_constants {
+    ($target:expr, $($name:ident),* $(,)*) => {{
+        let target = &$target;
+        (|| -> $crate::Result<()> {
+            $(
+                $crate::define_constant(target, stringify!($name), &$name)?;
+            )*
+            Ok(())
+        })()
+    }};
+}
@@ -0,0 +1,100 @@
+use rutie::{Object, RString, Symbol};
+use serde::de::{self, DeserializeOwned};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::de::{probe, split_variant};
+use crate::{enum_variants, raw, RutieObject};
+
+/// An externally tagged enum `T`, or a variant `T` doesn't know about yet.
+///
+/// serde's `#[serde(other)]` only supports a unit fallback variant, which loses the data. Instead,
+/// a variant name missing from `T` deserializes into `Unknown`, keeping the name and the raw Ruby
+/// content, so forward-compatible consumers can log or pass it on untouched. Known variants are
+/// deserialized into `T` as usual, and their errors are reported as usual.
+///
+/// `Unknown` serializes back to `{ tag => value }`, or just to `value` when it was a bare String
+/// or Symbol variant. Only works with `rutie_serde`'s own `Deserializer`.
+pub enum OrUnknown<T> {
+    Known(T),
+    Unknown { tag: String, value: RutieObject },
+}
+
+impl<T> OrUnknown<T> {
+    pub fn known(&self) -> Option<&T> {
+        match self {
+            OrUnknown::Known(value) => Some(value),
+            OrUnknown::Unknown { .. } => None,
+        }
+    }
+
+    pub fn into_known(self) -> Option<T> {
+        match self {
+            OrUnknown::Known(value) => Some(value),
+            OrUnknown::Unknown { .. } => None,
+        }
+    }
+
+    /// The name of the unknown variant, if this is one.
+    pub fn unknown_tag(&self) -> Option<&str> {
+        match self {
+            OrUnknown::Known(_) => None,
+            OrUnknown::Unknown { tag, .. } => Some(tag),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for OrUnknown<T>
+where
+    T: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let inner = raw::deserializer(deserializer)?;
+        let variants = enum_variants::<T>().map_err(de::Error::custom)?;
+        let (tag, value) = split_variant(inner.object()).map_err(de::Error::custom)?;
+        if variants.contains(&&*tag) {
+            T::deserialize(inner)
+                .map(OrUnknown::Known)
+                .map_err(de::Error::custom)
+        } else {
+            Ok(OrUnknown::Unknown {
+                tag,
+                value: RutieObject(value),
+            })
+        }
+    }
+}
+
+impl<T> Serialize for OrUnknown<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            OrUnknown::Known(value) => value.serialize(serializer),
+            OrUnknown::Unknown { tag, value } => {
+                let object = &value.0;
+                let is_bare_tag = (probe::<RString>(object).is_some()
+                    || probe::<Symbol>(object).is_some())
+                    && object
+                        .protect_send("to_s", &[])
+                        .ok()
+                        .and_then(|name| name.try_convert_to::<RString>().ok())
+                        .is_some_and(|name| name.to_str() == tag);
+                if is_bare_tag {
+                    value.serialize(serializer)
+                } else {
+                    let mut map = serializer.serialize_map(Some(1))?;
+                    map.serialize_entry(tag, value)?;
+                    map.end()
+                }
+            }
+        }
+    }
+}
@@ -1,31 +1,155 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
 use std::cell::RefCell;
-use std::panic::UnwindSafe;
+use std::fmt;
+use std::panic::{self, UnwindSafe};
+use std::sync::Once;
+use std::thread;
 
 use rutie::{self, Class, Object, RString, VM};
 
+use crate::error::attach_backtrace;
+
+/// A panic caught by `catch`.
+///
+/// The location and thread are only known when the panic hook of `install_hook` is installed,
+/// and the backtrace is only captured if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set.
+#[derive(Debug)]
+pub struct PanicInfo {
+    message: String,
+    location: Option<String>,
+    thread: Option<String>,
+    backtrace: Option<Backtrace>,
+}
+
+impl PanicInfo {
+    /// The panic's message, or "Unknown error" if it had none.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The `file:line` of the panic.
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// The name of the thread which panicked.
+    pub fn thread(&self) -> Option<&str> {
+        self.thread.as_deref()
+    }
+
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+}
+
+impl fmt::Display for PanicInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match (&self.location, &self.thread) {
+            (Some(location), Some(thread)) => {
+                write!(f, " (at {}, in thread '{}')", location, thread)
+            }
+            (Some(location), None) => write!(f, " (at {})", location),
+            _ => Ok(()),
+        }
+    }
+}
+
 thread_local! {
-    static RUTIE_SERDE_PANIC_MESSAGE: RefCell<Option<String>> = RefCell::new(None);
+    // The last panic on this thread, saved by the panic hook for `catch`.
+    static RUTIE_SERDE_PANIC: RefCell<Option<PanicInfo>> = const { RefCell::new(None) };
 }
 
+static INSTALL_HOOK: Once = Once::new();
+
+/// Saves the message of a panic, to be raised by `catch_and_raise`. Call this from a panic hook,
+/// or use `install_hook`.
+///
+/// A Rust backtrace of the panic is captured too (if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is
+/// set), and exposed as `#rust_backtrace` on the raised exception.
 pub fn save_panic_message(message: String) {
-    RUTIE_SERDE_PANIC_MESSAGE.with(|cell| cell.replace(Some(message)));
+    save_panic(PanicInfo {
+        message,
+        location: None,
+        thread: None,
+        backtrace: Some(Backtrace::capture()),
+    });
+}
+
+fn save_panic(saved: PanicInfo) {
+    RUTIE_SERDE_PANIC.with(|cell| cell.replace(Some(saved)));
+}
+
+/// Installs a panic hook saving the message, location and thread of each panic for
+/// `catch_and_raise`, which includes them in the raised exception. Call it from the extension's
+/// `Init_` function; calling it again has no effect.
+///
+/// The previously installed hook (by default, the one printing the panic to stderr) still runs
+/// after saving the panic.
+pub fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            save_panic(PanicInfo {
+                message: payload_message(info.payload())
+                    .unwrap_or_else(|| "Unknown error".to_owned()),
+                location: info
+                    .location()
+                    .map(|location| format!("{}:{}", location.file(), location.line())),
+                thread: thread::current().name().map(str::to_owned),
+                backtrace: Some(Backtrace::capture()),
+            });
+            previous(info);
+        }));
+    });
+}
+
+// The message of a panic payload, which is a `&str` or `String` for panics with a message.
+fn payload_message(payload: &(dyn Any + Send)) -> Option<String> {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| (*message).to_owned())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
 }
 
+/// Calls `f`, returning the panic it raised, if any, instead of unwinding further.
+///
+/// This is the non-raising counterpart of `catch_and_raise`, for Rust code invoked from Ruby
+/// which wants to handle panics itself.
+pub fn catch<T, F>(f: F) -> Result<T, PanicInfo>
+where
+    F: FnOnce() -> T,
+    F: UnwindSafe,
+{
+    panic::catch_unwind(f).map_err(|payload| {
+        RUTIE_SERDE_PANIC
+            .with(|cell| cell.replace(None))
+            .unwrap_or_else(|| PanicInfo {
+                message: payload_message(&*payload).unwrap_or_else(|| "Unknown error".to_owned()),
+                location: None,
+                thread: None,
+                backtrace: None,
+            })
+    })
+}
+
+/// Calls `f`, raising a panic in it as an exception of `exception_class` with the panic's message.
 pub fn catch_and_raise<T, F>(exception_class: Class, f: F) -> T
 where
     F: FnOnce() -> T,
     F: UnwindSafe,
 {
-    let res = std::panic::catch_unwind(f);
-    match res {
+    match catch(f) {
         Ok(v) => v,
-        Err(_) => {
-            let msg = RUTIE_SERDE_PANIC_MESSAGE.with(|panic_cell| match panic_cell.replace(None) {
-                Some(message) => message,
-                None => "Unknown error".to_owned(),
-            });
+        Err(info) => {
+            let msg = info.to_string();
             let instance = exception_class.new_instance(&[RString::new_utf8(&msg).to_any_object()]);
             let exception = rutie::AnyException::from(instance.value());
+            if let Some(backtrace) = &info.backtrace {
+                attach_backtrace(&exception, backtrace);
+            }
+            drop(info);
             VM::raise_ex(exception);
             unreachable!("VM::raise_ex");
         }
@@ -0,0 +1,133 @@
+//! Keeping Ruby objects alive, and in place, while Rust holds on to them.
+//!
+//! A `rutie::AnyObject` is just a `VALUE`: once Ruby no longer references the object, the GC is
+//! free to collect it even though Rust still has a copy. Since Ruby 2.7 the compacting GC may
+//! also move objects which are only referenced from other Ruby objects, leaving a stale `VALUE`
+//! behind in Rust.
+//!
+//! `Pinned` guards against both. Every handle stores its `VALUE` in its own heap slot registered
+//! with `rb_gc_register_address`. Registered addresses are marked conservatively, which both
+//! keeps the object alive and pins it in place during compaction, until the last `Pinned` handle
+//! for it is dropped.
+//!
+//! Values which only live on the Rust stack for the duration of a call (as in the `Deserializer`
+//! and `Serializer` themselves) don't need this: the machine stack is scanned conservatively, so
+//! such objects are pinned already. Anything kept on the heap beyond the current call, or keyed
+//! by address, should hold a `Pinned`.
+//!
+//! Like any other Ruby object access, `Pinned` handles must only be created, cloned and dropped
+//! on a Ruby thread holding the GVL.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Mutex, OnceLock};
+
+use rutie::rubysys::gc::{rb_gc_register_address, rb_gc_unregister_address};
+use rutie::types::{CallbackPtr, Value};
+use rutie::{AnyObject, Object};
+
+// Number of live handles per pinned `VALUE`.
+static PIN_COUNTS: OnceLock<Mutex<HashMap<usize, usize>>> = OnceLock::new();
+
+fn update_pin_count(object: &AnyObject, update: impl FnOnce(usize) -> usize) {
+    let mut counts = PIN_COUNTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let key = object.value().value;
+    let count = update(counts.get(&key).copied().unwrap_or(0));
+    if count == 0 {
+        counts.remove(&key);
+    } else {
+        counts.insert(key, count);
+    }
+}
+
+/// Pins `object` for the rest of the process, for objects cached in statics and thread-locals.
+///
+/// Unlike `rutie::GC::register_mark`, this also keeps the object from being moved by compaction,
+/// so cached copies of its `VALUE` stay valid.
+pub fn pin_forever<T>(object: &T)
+where
+    T: Object,
+{
+    let slot: &'static Value = Box::leak(Box::new(object.value()));
+    unsafe { rb_gc_register_address(slot_address(slot)) };
+}
+
+/// Returns the number of distinct objects currently pinned.
+pub fn pinned_count() -> usize {
+    PIN_COUNTS.get().map_or(0, |counts| {
+        counts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .len()
+    })
+}
+
+/// An owned handle which keeps a Ruby object from being garbage collected.
+///
+/// The object is neither collected nor moved by a compacting GC while the handle is alive.
+pub struct Pinned {
+    object: AnyObject,
+    // The registered slot must keep a stable address, hence the box.
+    slot: Box<Value>,
+    // Registering and unregistering addresses needs the GVL, so handles mustn't wander off to
+    // other threads.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Pinned {
+    pub fn new<T>(object: &T) -> Self
+    where
+        T: Object,
+    {
+        let object = object.to_any_object();
+        let slot = Box::new(object.value());
+        unsafe { rb_gc_register_address(slot_address(&slot)) };
+        update_pin_count(&object, |count| count + 1);
+        Pinned {
+            object,
+            slot,
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> &AnyObject {
+        &self.object
+    }
+
+    /// Calls `inspect` on the object, for use in error messages.
+    pub fn inspect(&self) -> String {
+        self.object
+            .protect_send("inspect", &[])
+            .ok()
+            .and_then(|inspect| inspect.try_convert_to::<rutie::RString>().ok())
+            .map(|rstring| rstring.to_string())
+            .unwrap_or_else(|| "#<uninspectable object>".to_owned())
+    }
+}
+
+impl Clone for Pinned {
+    fn clone(&self) -> Self {
+        Pinned::new(&self.object)
+    }
+}
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        unsafe { rb_gc_unregister_address(slot_address(&self.slot)) };
+        update_pin_count(&self.object, |count| count.saturating_sub(1));
+    }
+}
+
+fn slot_address(slot: &Value) -> CallbackPtr {
+    slot as *const Value as CallbackPtr
+}
+
+impl fmt::Debug for Pinned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Pinned").field(&self.inspect()).finish()
+    }
+}
@@ -0,0 +1,89 @@
+//! Calling `rutie_serde` methods from Ractors (Ruby 3.0 and later).
+//!
+//! Objects passed between Ractors must be shareable, i.e. deeply frozen. Set
+//! `SerializerConfig::ractor_shareable` to make serialized results shareable, or call
+//! `make_shareable` on individual objects. Methods can only be called from other Ractors once the
+//! extension is marked with `mark_extension_safe`.
+//!
+//! `rutie_serde`'s own caches are safe to use from any Ractor: they only hold classes, Symbols
+//! and shareable lambdas, and caught panics are handed over on the native thread they happened
+//! on. Objects registered in `handles`, however, are visible process wide, so handles must only
+//! be looked up from the Ractor which registered them.
+
+use rutie::{AnyObject, Class, Object, VM};
+
+extern "C" {
+    // Only defined by Ruby 3.0 and later, and only called when Ractors are supported, which the
+    // extension's lazy binding of Ruby's symbols allows.
+    fn rb_ext_ractor_safe(flag: bool);
+}
+
+use crate::pinned::pin_forever;
+use crate::{cache, Error, Result, ResultExt};
+
+/// Whether the running Ruby has Ractors.
+pub fn is_supported() -> bool {
+    cache::core_class("Object")
+        .protect_send("const_defined?", &[cache::symbol("Ractor").to_any_object()])
+        .map(|defined| defined.value().is_true())
+        .unwrap_or(false)
+}
+
+fn ractor_class() -> Result<Class> {
+    if !is_supported() {
+        return Err("Ractors require Ruby 3.0 or later".to_owned().into());
+    }
+    Ok(cache::core_class("Ractor"))
+}
+
+/// Marks the extension as Ractor-safe, so that methods defined afterwards can be called from
+/// Ractors other than the main one, which raise `Ractor::UnsafeError` otherwise. Does nothing
+/// on Rubies without Ractors.
+///
+/// Call it first thing in `Init_*`, as it applies to the methods defined until `Init_*` returns.
+/// `rutie_serde`'s own machinery is safe to use from any Ractor, but the methods' bodies must be
+/// too: they mustn't share Ruby objects through Rust statics, or use `handles` across Ractors.
+pub fn mark_extension_safe() {
+    if is_supported() {
+        unsafe { rb_ext_ractor_safe(true) };
+    }
+}
+
+/// Deeply freezes `object` with `Ractor.make_shareable`, failing for objects which can't be
+/// shared, such as Procs referring to unshareable objects.
+pub fn make_shareable<O>(object: &O) -> Result<AnyObject>
+where
+    O: Object,
+{
+    ractor_class()?
+        .protect_send("make_shareable", &[object.to_any_object()])
+        .map_err(Error::from)
+        .chain_context(|| "When making an object Ractor-shareable")
+}
+
+/// Whether `object` can be passed to other Ractors as it is.
+pub fn is_shareable<O>(object: &O) -> Result<bool>
+where
+    O: Object,
+{
+    Ok(ractor_class()?
+        .protect_send("shareable?", &[object.to_any_object()])?
+        .value()
+        .is_true())
+}
+
+/// Evaluates the lambda `source` into a pinned lambda, which is shareable when Ractors are
+/// supported, so that caching it doesn't tie it to the Ractor which happened to create it.
+pub(crate) fn shareable_lambda(source: &str) -> Result<AnyObject> {
+    let lambda = if is_supported() {
+        // A Proc can only be made shareable if its `self` is.
+        VM::eval(&format!(
+            "Ractor.make_shareable(nil.instance_exec {{ {} }})",
+            source
+        ))?
+    } else {
+        VM::eval(source)?
+    };
+    pin_forever(&lambda);
+    Ok(lambda)
+}
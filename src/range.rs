@@ -0,0 +1,135 @@
+use std::ops;
+
+use rutie::{AnyObject, Boolean, Class, NilClass, Object};
+use serde::de::{self, DeserializeOwned};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{new_ruby_object, raw, Error, Result, ResultExt};
+
+/// A Ruby `Range`, such as `1..10`, `1...10`, `1..` or `..10`.
+///
+/// A missing `start` or `end` is a beginless or endless range, and `exclusive` is set for ranges
+/// written with `...`. The bounds are deserialized into (and serialized from) `T` as usual. Only
+/// works with `rutie_serde`'s own `Serializer` and `Deserializer`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RubyRange<T> {
+    pub start: Option<T>,
+    pub end: Option<T>,
+    pub exclusive: bool,
+}
+
+impl<T> RubyRange<T>
+where
+    T: PartialOrd,
+{
+    /// Whether `value` lies within the range, as with Ruby's `Range#cover?`.
+    pub fn contains(&self, value: &T) -> bool {
+        let after_start = self.start.as_ref().is_none_or(|start| start <= value);
+        let before_end = self.end.as_ref().is_none_or(|end| {
+            if self.exclusive {
+                value < end
+            } else {
+                value <= end
+            }
+        });
+        after_start && before_end
+    }
+}
+
+impl<T> From<ops::Range<T>> for RubyRange<T> {
+    fn from(range: ops::Range<T>) -> Self {
+        RubyRange {
+            start: Some(range.start),
+            end: Some(range.end),
+            exclusive: true,
+        }
+    }
+}
+
+impl<T> From<ops::RangeInclusive<T>> for RubyRange<T> {
+    fn from(range: ops::RangeInclusive<T>) -> Self {
+        let (start, end) = range.into_inner();
+        RubyRange {
+            start: Some(start),
+            end: Some(end),
+            exclusive: false,
+        }
+    }
+}
+
+impl<T> From<ops::RangeFrom<T>> for RubyRange<T> {
+    fn from(range: ops::RangeFrom<T>) -> Self {
+        RubyRange {
+            start: Some(range.start),
+            end: None,
+            exclusive: false,
+        }
+    }
+}
+
+fn bound_to_ruby<T>(bound: &Option<T>) -> Result<AnyObject>
+where
+    T: Serialize,
+{
+    match bound {
+        Some(bound) => new_ruby_object(bound),
+        None => Ok(NilClass::new().to_any_object()),
+    }
+}
+
+fn range_to_ruby<T>(range: &RubyRange<T>) -> Result<AnyObject>
+where
+    T: Serialize,
+{
+    let start = bound_to_ruby(&range.start).chain_context(|| "When serializing the range start")?;
+    let end = bound_to_ruby(&range.end).chain_context(|| "When serializing the range end")?;
+    Ok(Class::from_existing("Range").protect_send(
+        "new",
+        &[start, end, Boolean::new(range.exclusive).to_any_object()],
+    )?)
+}
+
+impl<T> Serialize for RubyRange<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let range = range_to_ruby(self).map_err(ser::Error::custom)?;
+        raw::serialize(&range, serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for RubyRange<T>
+where
+    T: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let inner = raw::deserializer(deserializer)?;
+        let range = inner.object();
+        let read_bound = |method: &str| -> Result<Option<T>> {
+            let bound = range.protect_send(method, &[])?;
+            if bound.is_nil() {
+                return Ok(None);
+            }
+            T::deserialize(inner.child(&bound))
+                .map(Some)
+                .chain_context(|| format!("When deserializing the range {}", method))
+        };
+        let exclusive = range
+            .protect_send("exclude_end?", &[])
+            .map_err(Error::from)
+            .chain_context(|| "When deserializing a Range")
+            .map_err(de::Error::custom)?;
+        Ok(RubyRange {
+            start: read_bound("begin").map_err(de::Error::custom)?,
+            end: read_bound("end").map_err(de::Error::custom)?,
+            exclusive: exclusive.value().is_true(),
+        })
+    }
+}
@@ -0,0 +1,249 @@
+//! Passing raw Ruby objects through the serde data model.
+//!
+//! serde has no notion of "an opaque foreign value", so raw objects are smuggled through a
+//! newtype struct with a reserved name. When `rutie_serde`'s `Serializer` or `Deserializer` sees
+//! that name, the object itself is exchanged through a thread-local slot rather than being
+//! encoded in the data. Other serde formats never fill the slot, so they can't forge object
+//! references and get an error instead.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use rutie::AnyObject;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::handles;
+use crate::Deserializer as RutieDeserializer;
+
+pub(crate) const RAW_OBJECT_TOKEN: &str = "$rutie_serde::private::RawObject";
+// Wraps the elements of a `with::set` field, which `rutie_serde`'s `Serializer` turns into a Set.
+pub(crate) const SET_TOKEN: &str = "$rutie_serde::private::Set";
+
+enum RawSlot {
+    Empty,
+    Requested,
+    Filled(AnyObject),
+    FilledDeserializer(RutieDeserializer),
+}
+
+thread_local! {
+    static RAW_SLOT: RefCell<RawSlot> = const { RefCell::new(RawSlot::Empty) };
+}
+
+/// Called by `rutie_serde`'s `Serializer` before serializing a raw object payload.
+pub(crate) fn request() {
+    RAW_SLOT.with(|slot| slot.replace(RawSlot::Requested));
+}
+
+/// Hands `object` over to whoever calls `take` next.
+pub(crate) fn stash(object: AnyObject) {
+    RAW_SLOT.with(|slot| slot.replace(RawSlot::Filled(object)));
+}
+
+/// Like `stash`, but keeps the deserializer's configuration around for `take_deserializer`.
+pub(crate) fn stash_deserializer(deserializer: RutieDeserializer) {
+    RAW_SLOT.with(|slot| slot.replace(RawSlot::FilledDeserializer(deserializer)));
+}
+
+pub(crate) fn take() -> Option<AnyObject> {
+    take_deserializer().map(|deserializer| deserializer.object().clone())
+}
+
+pub(crate) fn take_deserializer() -> Option<RutieDeserializer> {
+    RAW_SLOT.with(|slot| match slot.replace(RawSlot::Empty) {
+        RawSlot::Filled(object) => Some(RutieDeserializer::new(&object)),
+        RawSlot::FilledDeserializer(deserializer) => Some(deserializer),
+        _ => None,
+    })
+}
+
+fn take_request() -> bool {
+    RAW_SLOT.with(|slot| {
+        let requested = matches!(*slot.borrow(), RawSlot::Requested);
+        if requested {
+            slot.replace(RawSlot::Empty);
+        }
+        requested
+    })
+}
+
+/// Serializes `object` as itself when `serializer` is `rutie_serde`'s, and fails otherwise.
+pub(crate) fn serialize<S>(object: &AnyObject, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(RAW_OBJECT_TOKEN, &RawPayload(object))
+}
+
+/// Deserializes the untouched Ruby object when `deserializer` is `rutie_serde`'s, and fails
+/// otherwise.
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<AnyObject, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_newtype_struct(RAW_OBJECT_TOKEN, RawObjectVisitor)
+}
+
+/// Like `deserialize`, but returns a `rutie_serde` `Deserializer` for the object which shares the
+/// outer deserializer's configuration.
+pub(crate) fn deserializer<'de, D>(deserializer: D) -> Result<RutieDeserializer, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_newtype_struct(RAW_OBJECT_TOKEN, RawDeserializerVisitor)
+}
+
+/// Like `serialize`, but other serializers see `fallback` (inside a newtype struct) instead of
+/// failing.
+pub(crate) fn serialize_or<S, F>(
+    object: &AnyObject,
+    fallback: &F,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    F: ?Sized + Serialize,
+{
+    serializer.serialize_newtype_struct(RAW_OBJECT_TOKEN, &RawOrFallback(object, fallback))
+}
+
+/// Like `serialize`, but other serializers see a `handles` handle for the object (inside a
+/// newtype struct), which `deserialize_or_handle` turns back into the object.
+pub(crate) fn serialize_or_handle<S>(object: &AnyObject, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serialize_or(object, &Handle(object), serializer)
+}
+
+/// Like `deserialize`, but other deserializers are expected to hold a handle written by
+/// `serialize_or_handle`, which is released.
+pub(crate) fn deserialize_or_handle<'de, D>(deserializer: D) -> Result<AnyObject, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_newtype_struct(RAW_OBJECT_TOKEN, RawOrHandleVisitor)
+}
+
+struct RawPayload<'a>(&'a AnyObject);
+
+impl<'a> Serialize for RawPayload<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if take_request() {
+            stash(self.0.clone());
+            serializer.serialize_unit()
+        } else {
+            Err(ser::Error::custom(
+                "raw Ruby objects can only be serialized by rutie_serde",
+            ))
+        }
+    }
+}
+
+struct RawOrFallback<'a, F: ?Sized>(&'a AnyObject, &'a F);
+
+impl<'a, F> Serialize for RawOrFallback<'a, F>
+where
+    F: ?Sized + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if take_request() {
+            stash(self.0.clone());
+            serializer.serialize_unit()
+        } else {
+            self.1.serialize(serializer)
+        }
+    }
+}
+
+// Registers the object only when serialized, so that `rutie_serde`'s `Serializer` doesn't leave
+// an unused handle behind.
+struct Handle<'a>(&'a AnyObject);
+
+impl<'a> Serialize for Handle<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        handles::register(self.0).serialize(serializer)
+    }
+}
+
+struct RawObjectVisitor;
+
+impl<'de> Visitor<'de> for RawObjectVisitor {
+    type Value = AnyObject;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a raw Ruby object")
+    }
+
+    fn visit_unit<E>(self) -> Result<AnyObject, E>
+    where
+        E: de::Error,
+    {
+        take().ok_or_else(|| E::custom("raw Ruby objects can only be deserialized by rutie_serde"))
+    }
+}
+
+struct RawDeserializerVisitor;
+
+impl<'de> Visitor<'de> for RawDeserializerVisitor {
+    type Value = RutieDeserializer;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a raw Ruby object")
+    }
+
+    fn visit_unit<E>(self) -> Result<RutieDeserializer, E>
+    where
+        E: de::Error,
+    {
+        take_deserializer()
+            .ok_or_else(|| E::custom("raw Ruby objects can only be deserialized by rutie_serde"))
+    }
+}
+
+struct RawOrHandleVisitor;
+
+impl<'de> Visitor<'de> for RawOrHandleVisitor {
+    type Value = AnyObject;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a raw Ruby object or a handle")
+    }
+
+    fn visit_unit<E>(self) -> Result<AnyObject, E>
+    where
+        E: de::Error,
+    {
+        take().ok_or_else(|| E::custom("expected a raw Ruby object or a handle"))
+    }
+
+    fn visit_u64<E>(self, handle: u64) -> Result<AnyObject, E>
+    where
+        E: de::Error,
+    {
+        handles::release(handle).ok_or_else(|| {
+            E::custom(format!(
+                "Handle {} was released or never registered",
+                handle
+            ))
+        })
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<AnyObject, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let handle = u64::deserialize(deserializer)?;
+        self.visit_u64(handle)
+    }
+}
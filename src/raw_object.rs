@@ -0,0 +1,82 @@
+use std::ops::{Deref, DerefMut};
+
+use rutie::{Object, VerifiedObject};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::with::passthrough;
+
+/// A Ruby object of type `T`, passed through untouched rather than serialized.
+///
+/// `Raw<T>` can be a method's return type, including as `Result<Raw<T>, E>` for any
+/// `E: IntoException`, or a field of a serialized struct, to mix existing Ruby objects into
+/// otherwise serialized data:
+///
+/// ```ignore
+/// #[derive(Serialize)]
+/// struct Response {
+///     status: u16,
+///     headers: Raw<rutie::Hash>,
+/// }
+/// ```
+///
+/// As a method argument or deserialized field, objects which aren't a `T` are rejected. Formats
+/// other than `rutie_serde`'s see a `handles` handle instead, as with `with::passthrough`.
+///
+/// Each such handle keeps its object pinned until it's deserialized back into a `Raw<T>` or
+/// released with `handles::release`, so serializing a `Raw<T>` to e.g. JSON which is never read
+/// back leaks the object. `handles::len` helps to spot this.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raw<T>(pub T);
+
+impl<T> Raw<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Raw<T>
+where
+    T: Object,
+{
+    fn from(object: T) -> Self {
+        Raw(object)
+    }
+}
+
+impl<T> Deref for Raw<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Raw<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> Serialize for Raw<T>
+where
+    T: Object,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        passthrough::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Raw<T>
+where
+    T: VerifiedObject,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        passthrough::deserialize(deserializer).map(Raw)
+    }
+}
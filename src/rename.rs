@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+/// How Rust struct field names map to the names used on the Ruby side, as Hash keys and reader
+/// methods.
+///
+/// This is a per-call alternative to annotating every field with `#[serde(rename)]`: set it as
+/// `DeserializerConfig::rename_fields` or `SerializerConfig::rename_fields`. Deserializing from a
+/// Hash accepts the renamed keys as well as the original field names.
+#[derive(Clone, Default)]
+pub enum RenameAll {
+    /// Use the field names as they are, the default.
+    #[default]
+    None,
+    /// `user_id` becomes `userId`.
+    CamelCase,
+    /// Predicate fields prefixed with `is_` lose the prefix and gain a trailing question mark, so
+    /// `is_active` becomes `active?`. Other fields are unchanged.
+    TrailingQuestionMark,
+    /// Any other mapping from field names to Ruby names.
+    Custom(Arc<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl RenameAll {
+    pub fn custom<F>(rename: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        RenameAll::Custom(Arc::new(rename))
+    }
+
+    /// The Ruby name of the field `name`.
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            RenameAll::None => Cow::Borrowed(name),
+            RenameAll::CamelCase => Cow::Owned(camel_case(name)),
+            RenameAll::TrailingQuestionMark => match name.strip_prefix("is_") {
+                Some(predicate) if !predicate.is_empty() => Cow::Owned(format!("{}?", predicate)),
+                _ => Cow::Borrowed(name),
+            },
+            RenameAll::Custom(rename) => Cow::Owned(rename(name)),
+        }
+    }
+
+    // The field name whose Ruby name is `name`, as far as it can be told without the list of
+    // fields: `Custom` renames can't be reversed, so their names are returned as they are.
+    pub(crate) fn unapply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            RenameAll::None | RenameAll::Custom(_) => Cow::Borrowed(name),
+            RenameAll::CamelCase => Cow::Owned(snake_case(name)),
+            RenameAll::TrailingQuestionMark => match name.strip_suffix('?') {
+                Some(predicate) if !predicate.is_empty() => Cow::Owned(format!("is_{}", predicate)),
+                _ => Cow::Borrowed(name),
+            },
+        }
+    }
+
+    pub(crate) fn is_none(&self) -> bool {
+        matches!(self, RenameAll::None)
+    }
+}
+
+impl fmt::Debug for RenameAll {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenameAll::None => f.write_str("None"),
+            RenameAll::CamelCase => f.write_str("CamelCase"),
+            RenameAll::TrailingQuestionMark => f.write_str("TrailingQuestionMark"),
+            RenameAll::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut capitalize = false;
+    for c in name.chars() {
+        if c == '_' {
+            // Keep leading underscores, as in `_private`.
+            if camel.is_empty() {
+                camel.push(c);
+            } else {
+                capitalize = true;
+            }
+        } else if capitalize {
+            camel.extend(c.to_uppercase());
+            capitalize = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 2);
+    for c in name.chars() {
+        if c.is_uppercase() && !snake.trim_start_matches('_').is_empty() {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
@@ -0,0 +1,123 @@
+//! A ready-made hierarchy of Ruby exception classes for `rutie_serde` errors.
+//!
+//! Calling `init` once from `Init_*` defines, under the given module:
+//!
+//! ```text
+//! MyGem::RutieSerde::Error < StandardError
+//! MyGem::RutieSerde::DeserializationError < MyGem::RutieSerde::Error
+//! MyGem::RutieSerde::SerializationError < MyGem::RutieSerde::Error
+//! MyGem::RutieSerde::PanicError < MyGem::RutieSerde::Error
+//! ```
+//!
+//! Afterwards, `#[rutie_serde::method]` functions without an explicit `exception` raise
+//! arguments which can't be deserialized as a `DeserializationError`, results which can't be
+//! serialized as a `SerializationError`, panics as a `PanicError`, and other errors as an
+//! `Error`. Without `init`, they all remain `StandardError`s. `rutie_serde_methods!` does the same
+//! when it's given no exception class.
+
+use std::sync::RwLock;
+
+use rutie::types::Value;
+use rutie::{Array, Class, Object, RString, VM};
+
+use crate::pinned::pin_forever;
+use crate::{Error, Result, ResultExt};
+
+/// The classes of the hierarchy defined by `init`, by the kind of failure they're raised for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// `RutieSerde::Error`, the superclass of the others.
+    Error,
+    /// `RutieSerde::DeserializationError`, for method arguments which can't be deserialized.
+    Deserialization,
+    /// `RutieSerde::SerializationError`, for method results which can't be serialized.
+    Serialization,
+    /// `RutieSerde::PanicError`, for panics in method bodies.
+    Panic,
+}
+
+impl ErrorClass {
+    fn index(self) -> usize {
+        match self {
+            ErrorClass::Error => 0,
+            ErrorClass::Deserialization => 1,
+            ErrorClass::Serialization => 2,
+            ErrorClass::Panic => 3,
+        }
+    }
+}
+
+static CLASSES: RwLock<Option<[Value; 4]>> = RwLock::new(None);
+
+// Finds or defines each constant along the way, so that `init` can be called again, e.g. after
+// the extension is reloaded, and classes defined in Ruby beforehand are used as they are.
+const DEFINE_CLASSES: &str = "->(path) do
+  defined = ->(parent, name, &define) do
+    parent.const_defined?(name, false) ? parent.const_get(name, false) : parent.const_set(name, define.call)
+  end
+  namespace = path.split('::').reduce(Object) { |parent, name| defined.(parent, name) { Module.new } }
+  root = defined.(namespace, :RutieSerde) { Module.new }
+  error = defined.(root, :Error) { Class.new(StandardError) }
+  [error] + %i[DeserializationError SerializationError PanicError].map do |name|
+    defined.(root, name) { Class.new(error) }
+  end
+end";
+
+/// Defines the `RutieSerde` exception classes under the module at `module_name` (such as
+/// `"MyGem"` or `"MyGem::Native"`, defining any missing modules), and makes them the default
+/// classes raised by `#[rutie_serde::method]` functions.
+///
+/// Classes which already exist are reused. Calling `init` again replaces the defaults.
+pub fn init(module_name: &str) -> Result<()> {
+    let classes = VM::eval(DEFINE_CLASSES)
+        .map_err(Error::from)?
+        .protect_send("call", &[RString::new_utf8(module_name).to_any_object()])
+        .map_err(Error::from)
+        .chain_context(|| format!("When defining the RutieSerde errors under {}", module_name))?
+        .try_convert_to::<Array>()?;
+    let mut values = [Value::from(0); 4];
+    for (slot, class) in values.iter_mut().zip(classes) {
+        pin_forever(&class);
+        *slot = class.value();
+    }
+    *CLASSES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(values);
+    Ok(())
+}
+
+/// The class defined by `init` for `kind`, or `None` if `init` wasn't called.
+pub fn class(kind: ErrorClass) -> Option<Class> {
+    CLASSES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .map(|values| Class::from(values[kind.index()]))
+}
+
+/// The class defined by `init` for `kind`, falling back to `StandardError`.
+pub fn default_class(kind: ErrorClass) -> Class {
+    class(kind).unwrap_or_else(|| Class::from_existing("StandardError"))
+}
+
+/// The exception classes raised by `rutie_serde_methods!` for each kind of failure: a single
+/// `rutie::Class` for all of them, or the `default_class` of each with `DefaultClasses`.
+pub trait ExceptionClasses {
+    fn class(&self, kind: ErrorClass) -> Class;
+}
+
+impl ExceptionClasses for Class {
+    fn class(&self, _kind: ErrorClass) -> Class {
+        Class::from(self.value())
+    }
+}
+
+/// Picks `default_class(kind)` for each kind of failure, as `rutie_serde_methods!` does when it's
+/// given no exception class.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultClasses;
+
+impl ExceptionClasses for DefaultClasses {
+    fn class(&self, kind: ErrorClass) -> Class {
+        default_class(kind)
+    }
+}
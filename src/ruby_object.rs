@@ -0,0 +1,68 @@
+use rutie::{AnyException, AnyObject, Class, Object, RString};
+
+use crate::de::object_class_name;
+use crate::{Error, Result};
+
+/// Typed wrappers around Ruby objects, which `rutie_serde_methods!` and `#[method]` accept as
+/// argument types without going through serde.
+///
+/// Derive it for a struct with a single `rutie::Object` field, naming the class the argument
+/// must be a kind of:
+///
+/// ```ignore
+/// #[derive(FromRubyObject)]
+/// #[ruby_class = "Rack::Request"]
+/// pub struct Request(AnyObject);
+///
+/// fn path(request: Request) -> String { ... }
+/// ```
+///
+/// Arguments which aren't a kind of the class raise a `TypeError`. Without `#[ruby_class]` any
+/// object is accepted. The derive also implements `MethodArgument`.
+pub trait FromRubyObject: Sized {
+    /// The constant path of the class or module objects must be a kind of (by `is_a?`), or
+    /// `None` to accept any object.
+    fn ruby_class() -> Option<&'static str>;
+
+    /// Wraps `object`, which has been checked to be a kind of `ruby_class`.
+    fn from_ruby_object_unchecked(object: AnyObject) -> Self;
+
+    /// Wraps `object`, failing with a `TypeError` if it isn't a kind of `ruby_class`.
+    fn from_ruby_object(object: AnyObject) -> Result<Self> {
+        let class_path = match Self::ruby_class() {
+            Some(class_path) => class_path,
+            None => return Ok(Self::from_ruby_object_unchecked(object)),
+        };
+        let class = Class::from_existing("Object")
+            .protect_send(
+                "const_get",
+                &[RString::new_utf8(class_path).to_any_object()],
+            )
+            .map_err(Error::from)?;
+        if object.protect_send("is_a?", &[class])?.value().is_true() {
+            return Ok(Self::from_ruby_object_unchecked(object));
+        }
+        let message = format!(
+            "wrong argument type {} (expected {})",
+            object_class_name(&object).unwrap_or_else(|_| "Unknown class".to_owned()),
+            class_path
+        );
+        let exception = Class::from_existing("TypeError")
+            .new_instance(&[RString::new_utf8(&message).to_any_object()]);
+        Err(Error::from(AnyException::from(exception.value())))
+    }
+}
+
+// `MethodArgument::from_method_arguments` for `#[derive(FromRubyObject)]` types.
+#[doc(hidden)]
+pub fn __from_ruby_object_argument<T>(
+    arguments: &[AnyObject],
+    position: &mut usize,
+) -> Option<Result<T>>
+where
+    T: FromRubyObject,
+{
+    let argument = arguments.get(*position)?;
+    *position += 1;
+    Some(T::from_ruby_object(argument.clone()))
+}
@@ -1,16 +1,277 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use rutie::types::Value;
 use rutie::{self, AnyObject, Encoding, Object};
 use serde::ser::{self, Serialize};
 
-use crate::{Error, Result};
+use crate::cache;
+use crate::class_map::{instantiate, ClassMap};
+use crate::de::probe;
+use crate::pinned::pin_forever;
+use crate::ractor;
+use crate::raw;
+use crate::with;
+use crate::{Error, MaxDepth, NonFiniteFloats, RenameAll, Result, ResultExt};
+
+/// The type of Hash keys produced for struct fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyStyle {
+    /// `{ :name => "value" }`, the default.
+    #[default]
+    Symbol,
+    /// `{ "name" => "value" }`.
+    String,
+}
+
+impl KeyStyle {
+    fn key(self, name: &str) -> AnyObject {
+        match self {
+            KeyStyle::Symbol => rutie::Symbol::new(name).to_any_object(),
+            KeyStyle::String => rutie::RString::new_utf8(name).to_any_object(),
+        }
+    }
+
+    // Like `key`, but reuses the Symbols (or frozen Strings) of field names.
+    fn field_key(self, name: Cow<'static, str>) -> AnyObject {
+        match (self, name) {
+            (KeyStyle::Symbol, Cow::Borrowed(name)) => cache::symbol(name).to_any_object(),
+            (KeyStyle::String, Cow::Borrowed(name)) => cache::frozen_string(name).to_any_object(),
+            (_, name) => self.key(&name),
+        }
+    }
+}
+
+/// The type of Hash keys produced for map keys which serialize as strings, such as `String`,
+/// `&str` or unit enum variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapKeyStyle {
+    /// Keys are serialized like any other value, so string keys become Strings. The default.
+    #[default]
+    Generic,
+    /// String keys become Symbols. Other keys, such as integers, are serialized as usual.
+    Symbol,
+}
+
+/// The type of Ruby object produced for Rust structs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StructTarget {
+    /// A Hash of the fields, the default.
+    #[default]
+    Hash,
+    /// An instance of a `Struct` class named after the Rust struct, e.g. `Struct::User`, with one
+    /// member per serialized field. Classes are created on first use and reused afterwards.
+    Struct,
+    /// An `OpenStruct` of the fields. Requires `ostruct`, which is loaded on first use.
+    OpenStruct,
+}
+
+/// Options controlling how Rust values are serialized into Ruby objects.
+#[derive(Debug, Clone, Default)]
+pub struct SerializerConfig {
+    pub key_style: KeyStyle,
+    pub map_key_style: MapKeyStyle,
+    /// Struct variants and maps are always serialized as Hashes.
+    pub struct_target: StructTarget,
+    /// How struct field names map to Hash keys (or members, and keyword arguments).
+    pub rename_fields: RenameAll,
+    /// How deeply sequences, maps and structs may be nested.
+    pub max_depth: MaxDepth,
+    /// What to do with NaN and infinite floats.
+    pub non_finite_floats: NonFiniteFloats,
+    /// Leave out struct fields which serialize into nil, such as `None`s, instead of storing nil
+    /// under their key, as `#[serde(skip_serializing_if = "Option::is_none")]` would. Members of
+    /// `StructTarget::Struct` classes are kept, as the class is defined by the fields present.
+    pub skip_none_fields: bool,
+    /// Freeze the Strings, Arrays and Hashes produced, so that they can be shared with other
+    /// threads without being mutated behind Rust's back. Objects of other classes (such as
+    /// `StructTarget::Struct` instances) are left as they are.
+    pub freeze_output: bool,
+    /// Deeply freeze the result with `ractor::make_shareable`, so it can be passed to other
+    /// Ractors, failing if it can't be shared. Only applies to `new_ruby_object_with_config`.
+    pub ractor_shareable: bool,
+    /// Disable the GC while serializing with `new_ruby_object_with_config`, for very large
+    /// results whose many small allocations would otherwise trigger collections along the way.
+    /// Off by default, as memory use can grow considerably meanwhile.
+    pub pause_gc: bool,
+}
+
+impl SerializerConfig {
+    // Applies `freeze_output` to a String, Array or Hash built by the serializer.
+    fn output(&self, mut object: AnyObject) -> AnyObject {
+        if self.freeze_output {
+            object.freeze();
+        }
+        object
+    }
+}
+
+impl From<KeyStyle> for SerializerConfig {
+    fn from(key_style: KeyStyle) -> Self {
+        SerializerConfig {
+            key_style,
+            ..SerializerConfig::default()
+        }
+    }
+}
+
+impl From<MapKeyStyle> for SerializerConfig {
+    fn from(map_key_style: MapKeyStyle) -> Self {
+        SerializerConfig {
+            map_key_style,
+            ..SerializerConfig::default()
+        }
+    }
+}
+
+impl From<StructTarget> for SerializerConfig {
+    fn from(struct_target: StructTarget) -> Self {
+        SerializerConfig {
+            struct_target,
+            ..SerializerConfig::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Serializer {
+    config: Rc<SerializerConfig>,
+    // The number of collections the value being serialized is nested in.
+    depth: usize,
+}
 
-pub struct Serializer;
+impl Serializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config<C>(config: C) -> Self
+    where
+        C: Into<SerializerConfig>,
+    {
+        Serializer {
+            config: Rc::new(config.into()),
+            depth: 0,
+        }
+    }
+
+    // The depth of the contents of a collection serialized by this serializer, failing past
+    // `max_depth` rather than recursing until the stack overflows.
+    fn nested(&self) -> Result<usize> {
+        let max_depth = self.config.max_depth.0;
+        if self.depth >= max_depth {
+            return Err(format!(
+                "Values are nested more than {} levels deep (see SerializerConfig::max_depth)",
+                max_depth
+            )
+            .into());
+        }
+        Ok(self.depth + 1)
+    }
+}
 
 pub fn new_ruby_object<T>(value: T) -> Result<AnyObject>
 where
     T: Serialize,
 {
-    let mut serializer = Serializer;
-    Ok(value.serialize(&mut serializer)?)
+    new_ruby_object_with_config(value, SerializerConfig::default())
+}
+
+/// Like `new_ruby_object`, but with non-default `SerializerConfig` options, e.g.
+/// `new_ruby_object_with_config(&value, KeyStyle::String)`.
+pub fn new_ruby_object_with_config<T, C>(value: T, config: C) -> Result<AnyObject>
+where
+    T: Serialize,
+    C: Into<SerializerConfig>,
+{
+    let mut serializer = Serializer::with_config(config);
+    let _gc_pause = if serializer.config.pause_gc {
+        Some(GcPause::start())
+    } else {
+        None
+    };
+    let object = value.serialize(&mut serializer)?;
+    if serializer.config.ractor_shareable {
+        return ractor::make_shareable(&object);
+    }
+    Ok(object)
+}
+
+// Disables the GC until dropped, unless it was disabled already.
+struct GcPause {
+    was_disabled: bool,
+}
+
+impl GcPause {
+    fn start() -> Self {
+        GcPause {
+            was_disabled: rutie::GC::disable(),
+        }
+    }
+}
+
+impl Drop for GcPause {
+    fn drop(&mut self) {
+        if !self.was_disabled {
+            rutie::GC::enable();
+        }
+    }
+}
+
+/// Serializes each of `values` and appends it to `array`, as the values are produced.
+///
+/// Rust code producing a large result set can call this once per chunk, dropping each chunk (and
+/// e.g. reporting progress) before producing the next, instead of building the whole collection
+/// up front. Values appended before an error are kept.
+pub fn serialize_into_array<I>(array: &mut rutie::Array, values: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut serializer = Serializer::new();
+    for (index, value) in values.into_iter().enumerate() {
+        let value = value
+            .serialize(&mut serializer)
+            .chain_context(|| format!("When serializing the value at index {}", index))?;
+        array.push(value);
+    }
+    Ok(())
+}
+
+/// Like `serialize_into_array`, but stores each of `entries` in `hash`, replacing the values of
+/// existing keys.
+pub fn serialize_into_hash<I, K, V>(hash: &mut rutie::Hash, entries: I) -> Result<()>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Serialize,
+    V: Serialize,
+{
+    let mut serializer = Serializer::new();
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        let key = key
+            .serialize(&mut serializer)
+            .chain_context(|| format!("When serializing the key of entry {}", index))?;
+        let value = value
+            .serialize(&mut serializer)
+            .chain_context(|| format!("When serializing the value of entry {}", index))?;
+        hash.store(key, value);
+    }
+    Ok(())
+}
+
+fn big_integer(digits: &str) -> Result<AnyObject> {
+    Ok(rutie::Module::from_existing("Kernel").protect_send(
+        "Integer",
+        &[rutie::RString::new_utf8(digits).to_any_object()],
+    )?)
+}
+
+/// A binary (ASCII-8BIT) String holding exactly `bytes`.
+pub(crate) fn binary_string(bytes: &[u8]) -> Result<AnyObject> {
+    let encoding = Encoding::find("ASCII-8BIT")?;
+    Ok(rutie::RString::from_bytes(bytes, &encoding).to_any_object())
 }
 
 impl<'a> ser::Serializer for &'a mut Serializer {
@@ -35,7 +296,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeTupleVariant = TupleVariantSerializer;
     type SerializeMap = MapSerializer;
     type SerializeStruct = MapSerializer;
-    type SerializeStructVariant = TupleStructSerializer;
+    type SerializeStructVariant = StructVariantSerializer;
 
     // Here we go with the simple methods. The following 12 methods receive one
     // of the primitive types of the data model and map it to JSON by appending
@@ -66,6 +327,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Ok(rutie::Fixnum::new(v).to_any_object())
     }
 
+    // Ruby Integers are arbitrary precision, so 128-bit integers go through `Kernel#Integer` with
+    // their decimal representation.
+    fn serialize_i128(self, v: i128) -> Result<AnyObject> {
+        big_integer(&v.to_string())
+    }
+
     fn serialize_u8(self, v: u8) -> Result<AnyObject> {
         self.serialize_i64(i64::from(v))
     }
@@ -78,8 +345,13 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         self.serialize_i64(i64::from(v))
     }
 
+    // Values above `i64::MAX` become Bignums.
     fn serialize_u64(self, v: u64) -> Result<AnyObject> {
-        self.serialize_i64(v as i64)
+        Ok(rutie::Integer::from(v).to_any_object())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<AnyObject> {
+        big_integer(&v.to_string())
     }
 
     fn serialize_f32(self, v: f32) -> Result<AnyObject> {
@@ -87,6 +359,19 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_f64(self, v: f64) -> Result<AnyObject> {
+        if !v.is_finite() {
+            match self.config.non_finite_floats {
+                NonFiniteFloats::Allow => {}
+                NonFiniteFloats::Error => {
+                    return Err(format!(
+                        "{} is not a finite number (see SerializerConfig::non_finite_floats)",
+                        v
+                    )
+                    .into())
+                }
+                NonFiniteFloats::ConvertToNil => return self.serialize_none(),
+            }
+        }
         Ok(rutie::Float::new(v).to_any_object())
     }
 
@@ -100,14 +385,15 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // get the idea. For example it would emit invalid JSON if the input string
     // contains a '"' character.
     fn serialize_str(self, v: &str) -> Result<AnyObject> {
-        Ok(rutie::RString::new_utf8(v).to_any_object())
+        Ok(self
+            .config
+            .output(rutie::RString::new_utf8(v).to_any_object()))
     }
 
-    // Serialize a byte array as an array of bytes. Could also use a base64
-    // string here. Binary formats will typically represent byte arrays more
-    // compactly.
+    // Byte slices (e.g. `serde_bytes::ByteBuf`) become binary Strings, which deserialize back to
+    // exactly the same bytes.
     fn serialize_bytes(self, v: &[u8]) -> Result<AnyObject> {
-        Ok(rutie::RString::from_bytes(v, &Encoding::default_external()).to_any_object())
+        Ok(self.config.output(binary_string(v)?))
     }
 
     // An absent optional is represented as the JSON `null`.
@@ -155,10 +441,19 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     // As is done here, serializers are encouraged to treat newtype structs as
     // insignificant wrappers around the data they contain.
-    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<AnyObject>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<AnyObject>
     where
         T: ?Sized + Serialize,
     {
+        if name == raw::RAW_OBJECT_TOKEN {
+            raw::request();
+            value.serialize(&mut *self)?;
+            return raw::take().ok_or_else(|| "raw Ruby object was not provided".into());
+        }
+        if name == raw::SET_TOKEN {
+            let array = value.serialize(&mut *self)?;
+            return Ok(self.config.output(with::set::new_set(array)?));
+        }
         value.serialize(self)
     }
 
@@ -177,9 +472,14 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
+        // Restored afterwards, as collections reuse their serializer for each element.
+        let depth = self.depth;
+        self.depth = self.nested()?;
+        let value = value.serialize(&mut *self);
+        self.depth = depth;
         let mut hash = rutie::Hash::new();
-        hash.store(rutie::Symbol::new(variant), value.serialize(self)?);
-        Ok(hash.to_any_object())
+        hash.store(cache::symbol(variant), value?);
+        Ok(self.config.output(hash.to_any_object()))
     }
 
     // Now we get to the serialization of compound types.
@@ -192,8 +492,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // doesn't make a difference in JSON because the length is not represented
     // explicitly in the serialized form. Some serializers may only be able to
     // support sequences for which the length is known up front.
-    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        Ok(SeqSerializer::new())
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SeqSerializer::new(
+            self.config.clone(),
+            self.nested()?,
+            len.unwrap_or(0),
+        ))
     }
 
     // Tuples look just like sequences in JSON. Some formats may be able to
@@ -219,15 +523,20 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
-        _len: usize,
+        variant: &'static str,
+        len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        Ok(TupleVariantSerializer::new())
+        Ok(TupleVariantSerializer::new(
+            self.config.clone(),
+            self.nested()?,
+            variant,
+            len,
+        ))
     }
 
     // Maps are represented in JSON as `{ K: V, K: V, ... }`.
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Ok(MapSerializer::new())
+        Ok(MapSerializer::new(self.config.clone(), self.nested()?))
     }
 
     // Structs look just like maps in JSON. In particular, JSON requires that we
@@ -235,8 +544,13 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // omit the field names when serializing structs because the corresponding
     // Deserialize implementation is required to know what the keys are without
     // looking at the serialized data.
-    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.serialize_map(Some(len))
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        Ok(MapSerializer::for_struct(
+            self.config.clone(),
+            self.nested()?,
+            name,
+            len,
+        ))
     }
 
     // Struct variants are represented in JSON as `{ NAME: { K: V, ... } }`.
@@ -245,21 +559,29 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        Ok(TupleStructSerializer::new())
+        Ok(StructVariantSerializer::new(
+            self.config.clone(),
+            self.nested()?,
+            variant,
+        ))
     }
 }
 
 pub struct SeqSerializer {
+    // Serializes each element, reusing the configuration and depth they're nested at.
+    child: Serializer,
     array: rutie::Array,
 }
 
 impl SeqSerializer {
-    fn new() -> Self {
+    // `capacity` is the expected number of elements, so that the Array is allocated only once.
+    fn new(config: Rc<SerializerConfig>, depth: usize, capacity: usize) -> Self {
         Self {
-            array: rutie::Array::new(),
+            child: Serializer { config, depth },
+            array: rutie::Array::with_capacity(capacity),
         }
     }
 }
@@ -282,13 +604,13 @@ impl<'a> ser::SerializeSeq for SeqSerializer {
     where
         T: ?Sized + Serialize,
     {
-        self.array.push(new_ruby_object(value)?);
+        self.array.push(value.serialize(&mut self.child)?);
         Ok(())
     }
 
     // Close the sequence.
     fn end(self) -> Result<AnyObject> {
-        Ok(self.array.to_any_object())
+        Ok(self.child.config.output(self.array.to_any_object()))
     }
 }
 
@@ -301,12 +623,12 @@ impl<'a> ser::SerializeTuple for SeqSerializer {
     where
         T: ?Sized + Serialize,
     {
-        self.array.push(new_ruby_object(value)?);
+        self.array.push(value.serialize(&mut self.child)?);
         Ok(())
     }
 
     fn end(self) -> Result<AnyObject> {
-        Ok(self.array.to_any_object())
+        Ok(self.child.config.output(self.array.to_any_object()))
     }
 }
 
@@ -319,60 +641,92 @@ impl<'a> ser::SerializeTupleStruct for SeqSerializer {
     where
         T: ?Sized + Serialize,
     {
-        self.array.push(new_ruby_object(value)?);
+        self.array.push(value.serialize(&mut self.child)?);
         Ok(())
     }
 
     fn end(self) -> Result<AnyObject> {
-        Ok(self.array.to_any_object())
+        Ok(self.child.config.output(self.array.to_any_object()))
     }
 }
 
 pub struct TupleVariantSerializer {
-    object: AnyObject,
+    variant: &'static str,
+    fields: SeqSerializer,
 }
 
 impl TupleVariantSerializer {
-    fn new() -> Self {
-        unimplemented!("TupleVariantSerializer::new")
+    fn new(config: Rc<SerializerConfig>, depth: usize, variant: &'static str, len: usize) -> Self {
+        Self {
+            variant,
+            fields: SeqSerializer::new(config, depth, len),
+        }
     }
 }
 
 // Tuple variants are a little different. Refer back to the
-// `serialize_tuple_variant` method above:
-//
-//    self.output += "{";
-//    variant.serialize(&mut *self)?;
-//    self.output += ":[";
-//
-// So the `end` method in this impl is responsible for closing both the `]` and
-// the `}`.
-impl<'a> ser::SerializeTupleVariant for TupleVariantSerializer {
+// `serialize_tuple_variant` method above: the fields are collected into an
+// Array, and the `end` method is responsible for wrapping it in the
+// `{ NAME: [DATA...] }` hash.
+impl ser::SerializeTupleVariant for TupleVariantSerializer {
     type Ok = AnyObject;
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!("SerializeTupleVariant::serialize_field")
+        ser::SerializeTupleStruct::serialize_field(&mut self.fields, value)
     }
 
     fn end(self) -> Result<AnyObject> {
-        Ok(self.object)
+        let config = self.fields.child.config.clone();
+        let mut hash = rutie::Hash::new();
+        hash.store(
+            cache::symbol(self.variant),
+            ser::SerializeTupleStruct::end(self.fields)?,
+        );
+        Ok(config.output(hash.to_any_object()))
     }
 }
 
 pub struct MapSerializer {
+    // Serializes each key and value, as `SeqSerializer::child` does.
+    child: Serializer,
     hash: rutie::Hash,
     current_key: Option<AnyObject>,
+    // Set when serializing a struct (rather than a map or a struct variant's fields).
+    struct_name: Option<&'static str>,
+    // Only collected for `StructTarget::Struct`, which defines a class per member list.
+    field_names: Vec<String>,
 }
 
 impl MapSerializer {
-    fn new() -> Self {
+    fn new(config: Rc<SerializerConfig>, depth: usize) -> Self {
         Self {
+            child: Serializer { config, depth },
             hash: rutie::Hash::new(),
             current_key: None,
+            struct_name: None,
+            field_names: vec![],
+        }
+    }
+
+    fn for_struct(
+        config: Rc<SerializerConfig>,
+        depth: usize,
+        name: &'static str,
+        len: usize,
+    ) -> Self {
+        let field_names = if config.struct_target == StructTarget::Struct {
+            Vec::with_capacity(len)
+        } else {
+            vec![]
+        };
+        Self {
+            struct_name: Some(name),
+            field_names,
+            ..Self::new(config, depth)
         }
     }
 }
@@ -401,7 +755,15 @@ impl<'a> ser::SerializeMap for MapSerializer {
     where
         T: ?Sized + Serialize,
     {
-        self.current_key = Some(new_ruby_object(key)?);
+        let key = key.serialize(&mut self.child)?;
+        let key = match self.child.config.map_key_style {
+            MapKeyStyle::Symbol => match probe::<rutie::RString>(&key) {
+                Some(string) => rutie::Symbol::new(&string.to_string()).to_any_object(),
+                None => key,
+            },
+            MapKeyStyle::Generic => key,
+        };
+        self.current_key = Some(key);
         Ok(())
     }
 
@@ -414,7 +776,8 @@ impl<'a> ser::SerializeMap for MapSerializer {
     {
         match self.current_key {
             Some(ref key) => {
-                self.hash.store(key.clone(), new_ruby_object(value)?);
+                self.hash
+                    .store(key.clone(), value.serialize(&mut self.child)?);
                 Ok(())
             }
             None => Err("no key given".into()),
@@ -422,7 +785,7 @@ impl<'a> ser::SerializeMap for MapSerializer {
     }
 
     fn end(self) -> Result<AnyObject> {
-        Ok(self.hash.to_any_object())
+        Ok(self.child.config.output(self.hash.to_any_object()))
     }
 }
 
@@ -436,41 +799,182 @@ impl<'a> ser::SerializeStruct for MapSerializer {
     where
         T: ?Sized + Serialize,
     {
-        // TODO: Make it configurable what keys we expect: strings or symbols (or just standardise one)
+        let value = value.serialize(&mut self.child)?;
+        let is_struct_member =
+            self.struct_name.is_some() && self.child.config.struct_target == StructTarget::Struct;
+        if self.child.config.skip_none_fields && value.is_nil() && !is_struct_member {
+            return Ok(());
+        }
+        let key = self.child.config.rename_fields.apply(key);
+        if is_struct_member {
+            self.field_names.push(key.to_string());
+        }
         self.hash
-            .store(rutie::Symbol::new(key), new_ruby_object(value)?);
+            .store(self.child.config.key_style.field_key(key), value);
         Ok(())
     }
 
     fn end(self) -> Result<AnyObject> {
-        Ok(self.hash.to_any_object())
+        let name = match self.struct_name {
+            Some(name) => name,
+            None => return Ok(self.child.config.output(self.hash.to_any_object())),
+        };
+        if let Some(class_path) = ClassMap::lookup(name) {
+            return instantiate(&class_path, &self.hash.to_any_object());
+        }
+        match self.child.config.struct_target {
+            StructTarget::Hash => Ok(self.child.config.output(self.hash.to_any_object())),
+            StructTarget::Struct => {
+                let class = struct_class(name, &self.field_names)?;
+                let values = self
+                    .hash
+                    .protect_send("values", &[])?
+                    .try_convert_to::<rutie::Array>()?;
+                let values: Vec<AnyObject> = values.into_iter().collect();
+                Ok(class
+                    .protect_send("new", &values)
+                    .map_err(Error::from)
+                    .chain_context(|| format!("When instantiating Struct::{}", name))?)
+            }
+            StructTarget::OpenStruct => {
+                require_ostruct()?;
+                Ok(rutie::Class::from_existing("OpenStruct")
+                    .protect_send("new", &[self.hash.to_any_object()])
+                    .map_err(Error::from)
+                    .chain_context(|| format!("When building an OpenStruct for {}", name))?)
+            }
+        }
     }
 }
 
-pub struct TupleStructSerializer {
-    object: AnyObject,
+// `Struct` classes created for `StructTarget::Struct`, by struct name and member list. Shared by
+// all threads, as each `Struct.new("Name", ...)` redefines the `Struct::Name` constant.
+static STRUCT_CLASSES: OnceLock<Mutex<StructClasses>> = OnceLock::new();
+
+type StructClasses = HashMap<(&'static str, Vec<String>), Value>;
+
+fn struct_classes() -> MutexGuard<'static, StructClasses> {
+    STRUCT_CLASSES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
-impl TupleStructSerializer {
-    fn new() -> Self {
-        unimplemented!("TupleStructSerializer::new")
+fn struct_class(name: &'static str, fields: &[String]) -> Result<AnyObject> {
+    let key = (name, fields.to_vec());
+    if let Some(&class) = struct_classes().get(&key) {
+        return Ok(AnyObject::from(class));
+    }
+    // Not held while calling into Ruby, which may run other threads' serializers meanwhile.
+    let class = match existing_struct_class(name, fields)? {
+        Some(class) => class,
+        None => {
+            let mut arguments = vec![rutie::RString::new_utf8(name).to_any_object()];
+            arguments.extend(
+                fields
+                    .iter()
+                    .map(|field| rutie::Symbol::new(field).to_any_object()),
+            );
+            cache::core_class("Struct")
+                .protect_send("new", &arguments)
+                .map_err(Error::from)
+                .chain_context(|| format!("When defining Struct::{}", name))?
+        }
+    };
+    let mut classes = struct_classes();
+    let class = *classes.entry(key).or_insert_with(|| {
+        // The class is also reachable as a constant, but that may be redefined by a struct of
+        // the same name with different fields.
+        pin_forever(&class);
+        class.value()
+    });
+    Ok(AnyObject::from(class))
+}
+
+// `Struct::Name` if it's already defined with these members, e.g. by an earlier load of the
+// extension or by Ruby code, so that it isn't redefined (with a warning) as a distinct class.
+fn existing_struct_class(name: &str, fields: &[String]) -> Result<Option<AnyObject>> {
+    let struct_class = cache::core_class("Struct");
+    let arguments = [
+        rutie::RString::new_utf8(name).to_any_object(),
+        rutie::Boolean::new(false).to_any_object(),
+    ];
+    if !struct_class
+        .protect_send("const_defined?", &arguments)?
+        .value()
+        .is_true()
+    {
+        return Ok(None);
+    }
+    let class = struct_class.protect_send("const_get", &arguments)?;
+    if !class.respond_to("members") {
+        return Ok(None);
+    }
+    let members = class
+        .protect_send("members", &[])?
+        .try_convert_to::<rutie::Array>()?;
+    let same_members = members.length() == fields.len()
+        && members.into_iter().zip(fields).all(|(member, field)| {
+            probe::<rutie::Symbol>(&member).is_some_and(|member| member.to_str() == field)
+        });
+    Ok(if same_members { Some(class) } else { None })
+}
+
+fn require_ostruct() -> Result<()> {
+    if cache::core_class("Object")
+        .protect_send(
+            "const_defined?",
+            &[rutie::RString::new_utf8("OpenStruct").to_any_object()],
+        )?
+        .value()
+        .is_true()
+    {
+        return Ok(());
+    }
+    rutie::Module::from_existing("Kernel")
+        .protect_send(
+            "require",
+            &[rutie::RString::new_utf8("ostruct").to_any_object()],
+        )
+        .map_err(Error::from)
+        .chain_context(|| "When loading ostruct")?;
+    Ok(())
+}
+
+pub struct StructVariantSerializer {
+    variant: &'static str,
+    fields: MapSerializer,
+}
+
+impl StructVariantSerializer {
+    fn new(config: Rc<SerializerConfig>, depth: usize, variant: &'static str) -> Self {
+        Self {
+            variant,
+            fields: MapSerializer::new(config, depth),
+        }
     }
 }
 
 // Similar to `SerializeTupleVariant`, here the `end` method is responsible for
-// closing both of the curly braces opened by `serialize_struct_variant`.
-impl<'a> ser::SerializeStructVariant for TupleStructSerializer {
+// wrapping the fields in the `{ NAME: { K: V, ... } }` hash.
+impl ser::SerializeStructVariant for StructVariantSerializer {
     type Ok = AnyObject;
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _key: &'static str, _value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!("SerializeStructVariant::serialize_field")
+        ser::SerializeStruct::serialize_field(&mut self.fields, key, value)
     }
 
     fn end(self) -> Result<AnyObject> {
-        Ok(self.object)
+        let config = self.fields.child.config.clone();
+        let mut hash = rutie::Hash::new();
+        hash.store(
+            cache::symbol(self.variant),
+            ser::SerializeStruct::end(self.fields)?,
+        );
+        Ok(config.output(hash.to_any_object()))
     }
 }
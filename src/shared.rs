@@ -0,0 +1,142 @@
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use rutie::Object;
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::pinned::Pinned;
+use crate::{from_object, raw, Result};
+
+// Keyed by the object's `VALUE` and the cached type.
+type SharedValues = HashMap<(usize, TypeId), (Pinned, Box<dyn Any>)>;
+
+thread_local! {
+    static SHARING_DEPTH: Cell<usize> = const { Cell::new(0) };
+    // Cached objects stay pinned while the scope is active, so their addresses remain valid keys
+    // even if the GC compacts the heap in the meantime.
+    static SHARED_VALUES: RefCell<SharedValues> = RefCell::new(HashMap::new());
+}
+
+/// Like `from_object`, but every `Shared<T>` inside `T` is deserialized at most once per Ruby
+/// object: later occurrences of the same object (by identity) reuse the same `Arc`.
+///
+/// This preserves aliasing present on the Ruby side and avoids repeating work for large shared
+/// sub-structures. Outside of this function `Shared<T>` always deserializes a fresh value.
+pub fn from_object_shared<T, O>(object: &O) -> Result<T>
+where
+    T: DeserializeOwned,
+    O: Object,
+{
+    let _scope = SharingScope::enter();
+    from_object(object)
+}
+
+struct SharingScope;
+
+impl SharingScope {
+    fn enter() -> Self {
+        SHARING_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        SharingScope
+    }
+
+    fn is_active() -> bool {
+        SHARING_DEPTH.with(|depth| depth.get() > 0)
+    }
+}
+
+impl Drop for SharingScope {
+    fn drop(&mut self) {
+        let remaining = SHARING_DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            depth.get()
+        });
+        // Ruby objects may be collected (and their addresses reused) once the outermost call
+        // returns, so the cache must not outlive it.
+        if remaining == 0 {
+            SHARED_VALUES.with(|values| values.borrow_mut().clear());
+        }
+    }
+}
+
+/// An `Arc<T>` which can be shared between every occurrence of the same Ruby object when
+/// deserialized through `from_object_shared`. Serialization is transparent.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Shared<T>(pub Arc<T>);
+
+impl<T> Shared<T> {
+    pub fn into_arc(self) -> Arc<T> {
+        self.0
+    }
+
+    /// Whether both handles point at the same allocation.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared(self.0.clone())
+    }
+}
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Shared<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let inner = raw::deserializer(deserializer)?;
+        if !SharingScope::is_active() {
+            let value = T::deserialize(inner).map_err(de::Error::custom)?;
+            return Ok(Shared(Arc::new(value)));
+        }
+
+        let key = (inner.object().value().value, TypeId::of::<T>());
+        let cached = SHARED_VALUES.with(|values| {
+            values
+                .borrow()
+                .get(&key)
+                .and_then(|(_, value)| value.downcast_ref::<Arc<T>>())
+                .cloned()
+        });
+        if let Some(value) = cached {
+            return Ok(Shared(value));
+        }
+
+        let pinned = Pinned::new(inner.object());
+        let value = Arc::new(T::deserialize(inner).map_err(de::Error::custom)?);
+        SHARED_VALUES.with(|values| {
+            values
+                .borrow_mut()
+                .insert(key, (pinned, Box::new(value.clone())))
+        });
+        Ok(Shared(value))
+    }
+}
+
+impl<T> Serialize for Shared<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
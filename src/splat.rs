@@ -0,0 +1,71 @@
+use std::ops::{Deref, DerefMut};
+
+use rutie::{AnyObject, Array};
+use serde::de::DeserializeOwned;
+
+use crate::{from_object_with_config, DeserializerConfig, MethodArgument, Result};
+
+/// A `rutie_serde_methods!` argument which collects all remaining positional arguments, like
+/// Ruby's `*args`.
+///
+/// The arguments are gathered into an Array, which is deserialized into `T`, e.g. a `Vec` or a
+/// tuple:
+///
+/// ```ignore
+/// fn sum(numbers: Splat<Vec<f64>>) -> f64 {
+///     numbers.iter().sum()
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Splat<T>(pub T);
+
+impl<T> Splat<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Splat<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Splat<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'a, T> MethodArgument<'a> for Splat<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_method_arguments(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+    ) -> Option<Result<Self>> {
+        Self::from_method_arguments_with_config(arguments, position, &DeserializerConfig::default())
+    }
+
+    fn from_method_arguments_with_config(
+        arguments: &'a [AnyObject],
+        position: &mut usize,
+        config: &DeserializerConfig,
+    ) -> Option<Result<Self>> {
+        let rest: Array = arguments
+            .get(*position..)
+            .unwrap_or_default()
+            .iter()
+            .cloned()
+            .collect();
+        *position = arguments.len();
+        Some(from_object_with_config(&rest, config.clone()).map(Splat))
+    }
+
+    fn positional_arguments() -> Option<usize> {
+        None
+    }
+}
@@ -0,0 +1,72 @@
+//! `#[serde(with = "rutie_serde::time_serde")]` for `chrono::DateTime<Utc>` fields, exchanged
+//! with Ruby as `Time` objects. Requires the `chrono` feature.
+//!
+//! Serialization produces a UTC `Time`. Deserialization accepts anything that converts to a
+//! `Time` through `to_time`, such as `Time`, `DateTime` and `ActiveSupport::TimeWithZone`, and
+//! keeps nanosecond precision. Only works with `rutie_serde`'s own `Serializer` and
+//! `Deserializer`.
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! struct Order {
+//!     #[serde(with = "rutie_serde::time_serde")]
+//!     placed_at: DateTime<Utc>,
+//! }
+//! ```
+
+use chrono::{DateTime, TimeZone, Utc};
+use rutie::{AnyObject, Class, Fixnum, Integer, Object, Symbol};
+use serde::{de, ser, Deserializer, Serializer};
+
+use crate::de::integer_to_i64;
+use crate::{raw, Error, Result, ResultExt};
+
+pub fn serialize<S, Tz>(
+    value: &DateTime<Tz>,
+    serializer: S,
+) -> ::std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    Tz: TimeZone,
+{
+    let time = to_ruby_time(&value.with_timezone(&Utc))
+        .chain_context(|| format!("When converting {} to a Time", value.to_rfc3339()))
+        .map_err(ser::Error::custom)?;
+    raw::serialize(&time, serializer)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let object = raw::deserialize(deserializer)?;
+    from_ruby_time(&object)
+        .chain_context(|| "When deserializing a Time")
+        .map_err(de::Error::custom)
+}
+
+fn to_ruby_time(value: &DateTime<Utc>) -> Result<AnyObject> {
+    let time = Class::from_existing("Time").protect_send(
+        "at",
+        &[
+            Fixnum::new(value.timestamp()).to_any_object(),
+            Fixnum::new(i64::from(value.timestamp_subsec_nanos())).to_any_object(),
+            Symbol::new("nsec").to_any_object(),
+        ],
+    )?;
+    Ok(time.protect_send("utc", &[])?)
+}
+
+fn from_ruby_time(object: &AnyObject) -> Result<DateTime<Utc>> {
+    let time = object.protect_send("to_time", &[])?;
+    let seconds = time
+        .protect_send("to_i", &[])?
+        .try_convert_to::<Integer>()?;
+    let nanoseconds = time
+        .protect_send("nsec", &[])?
+        .try_convert_to::<Integer>()?;
+    let seconds = integer_to_i64(&seconds)?;
+    Utc.timestamp_opt(seconds, nanoseconds.to_u64() as u32)
+        .single()
+        .ok_or_else(|| Error::from(format!("{} seconds is out of range", seconds)))
+}
@@ -0,0 +1,371 @@
+//! Building Ruby objects from arbitrary serde data, and the reverse.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use rutie::{AnyObject, Array, Boolean, Fixnum, Float, Hash, Integer, NilClass, Object, RString};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::ser::binary_string;
+use crate::Deserializer as RutieDeserializer;
+use crate::{new_ruby_object, raw, Error, Result as RutieResult, ResultExt};
+
+/// Feeds `object`, and everything it contains, to the serializer of another serde format, such as
+/// MessagePack or CBOR, without defining an intermediate Rust type.
+///
+/// Only core types (nil, booleans, numbers, Strings, Symbols, Arrays, Hashes and Sets) can be
+/// transcoded; Symbols become strings.
+pub fn transcode_from_ruby<O, S>(object: &O, serializer: S) -> RutieResult<S::Ok>
+where
+    O: Object,
+    S: Serializer,
+{
+    Transcoder::new(RutieDeserializer::new(object))
+        .serialize(serializer)
+        .map_err(|error| Error::from(error.to_string()))
+        .chain_context(|| "When transcoding a Ruby object")
+}
+
+/// The reverse of `transcode_from_ruby`: builds the equivalent Ruby object from whatever
+/// `deserializer` holds. Maps become Hashes, whatever the type of their keys.
+pub fn transcode_to_ruby<'de, D>(deserializer: D) -> RutieResult<AnyObject>
+where
+    D: Deserializer<'de>,
+{
+    RubyObjectSeed
+        .deserialize(deserializer)
+        .map_err(|error| Error::from(error.to_string()))
+        .chain_context(|| "When transcoding into a Ruby object")
+}
+
+/// Deserializes any self-describing serde data into the equivalent Ruby object.
+pub(crate) struct RubyObjectSeed;
+
+impl<'de> DeserializeSeed<'de> for RubyObjectSeed {
+    type Value = AnyObject;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<AnyObject, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(RubyObjectVisitor)
+    }
+}
+
+/// Visits serde data and produces the equivalent Ruby object.
+///
+/// When driven through `raw::RAW_OBJECT_TOKEN` by `rutie_serde`'s own `Deserializer`, the
+/// original object is picked up untouched instead.
+pub(crate) struct RubyObjectVisitor;
+
+impl<'de> Visitor<'de> for RubyObjectVisitor {
+    type Value = AnyObject;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value representable in Ruby")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<AnyObject, E> {
+        Ok(Boolean::new(v).to_any_object())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<AnyObject, E> {
+        Ok(Fixnum::new(v).to_any_object())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<AnyObject, E> {
+        Ok(Integer::from(v).to_any_object())
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<AnyObject, E>
+    where
+        E: de::Error,
+    {
+        new_ruby_object(v).map_err(E::custom)
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<AnyObject, E>
+    where
+        E: de::Error,
+    {
+        new_ruby_object(v).map_err(E::custom)
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<AnyObject, E> {
+        Ok(Float::new(v).to_any_object())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<AnyObject, E> {
+        Ok(RString::new_utf8(v).to_any_object())
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<AnyObject, E>
+    where
+        E: de::Error,
+    {
+        binary_string(v).map_err(E::custom)
+    }
+
+    fn visit_none<E>(self) -> Result<AnyObject, E> {
+        Ok(NilClass::new().to_any_object())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<AnyObject, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_unit<E>(self) -> Result<AnyObject, E> {
+        Ok(raw::take().unwrap_or_else(|| NilClass::new().to_any_object()))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<AnyObject, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<AnyObject, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut array = Array::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element_seed(RubyObjectSeed)? {
+            array.push(element);
+        }
+        Ok(array.to_any_object())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<AnyObject, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut hash = Hash::new();
+        while let Some((key, value)) = map.next_entry_seed(RubyObjectSeed, RubyObjectSeed)? {
+            hash.store(key, value);
+        }
+        Ok(hash.to_any_object())
+    }
+}
+
+/// Serializes whatever data a deserializer produces, so that it can be passed straight to the
+/// serializer of another format, e.g. from `rutie_serde`'s `Deserializer` into JSON.
+pub(crate) struct Transcoder<D>(RefCell<Option<D>>);
+
+impl<D> Transcoder<D> {
+    pub(crate) fn new(deserializer: D) -> Self {
+        Transcoder(RefCell::new(Some(deserializer)))
+    }
+}
+
+impl<'de, D> Serialize for Transcoder<D>
+where
+    D: Deserializer<'de>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let deserializer = self
+            .0
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| ser::Error::custom("a value can only be transcoded once"))?;
+        deserializer
+            .deserialize_any(TranscodeVisitor(serializer))
+            .map_err(ser::Error::custom)
+    }
+}
+
+// Forwards each visited value to the serializer.
+struct TranscodeVisitor<S>(S);
+
+impl<'de, S> Visitor<'de> for TranscodeVisitor<S>
+where
+    S: Serializer,
+{
+    type Value = S::Ok;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_bool(v).map_err(E::custom)
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_i64(v).map_err(E::custom)
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_i128(v).map_err(E::custom)
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_u64(v).map_err(E::custom)
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_u128(v).map_err(E::custom)
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_f64(v).map_err(E::custom)
+    }
+
+    fn visit_char<E>(self, v: char) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_char(v).map_err(E::custom)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_str(v).map_err(E::custom)
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_bytes(v).map_err(E::custom)
+    }
+
+    fn visit_none<E>(self) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_none().map_err(E::custom)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<S::Ok, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0
+            .serialize_some(&Transcoder::new(deserializer))
+            .map_err(de::Error::custom)
+    }
+
+    fn visit_unit<E>(self) -> Result<S::Ok, E>
+    where
+        E: de::Error,
+    {
+        self.0.serialize_unit().map_err(E::custom)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<S::Ok, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Transcoder::new(deserializer)
+            .serialize(self.0)
+            .map_err(de::Error::custom)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<S::Ok, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut elements = self
+            .0
+            .serialize_seq(seq.size_hint())
+            .map_err(de::Error::custom)?;
+        while let Some(()) = seq.next_element_seed(ElementSeed(&mut elements))? {}
+        elements.end().map_err(de::Error::custom)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<S::Ok, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = self
+            .0
+            .serialize_map(map.size_hint())
+            .map_err(de::Error::custom)?;
+        while let Some(()) = map.next_key_seed(KeySeed(&mut entries))? {
+            map.next_value_seed(ValueSeed(&mut entries))?;
+        }
+        entries.end().map_err(de::Error::custom)
+    }
+}
+
+struct ElementSeed<'a, S>(&'a mut S);
+
+impl<'a, 'de, S> DeserializeSeed<'de> for ElementSeed<'a, S>
+where
+    S: SerializeSeq,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0
+            .serialize_element(&Transcoder::new(deserializer))
+            .map_err(de::Error::custom)
+    }
+}
+
+struct KeySeed<'a, S>(&'a mut S);
+
+impl<'a, 'de, S> DeserializeSeed<'de> for KeySeed<'a, S>
+where
+    S: SerializeMap,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0
+            .serialize_key(&Transcoder::new(deserializer))
+            .map_err(de::Error::custom)
+    }
+}
+
+struct ValueSeed<'a, S>(&'a mut S);
+
+impl<'a, 'de, S> DeserializeSeed<'de> for ValueSeed<'a, S>
+where
+    S: SerializeMap,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0
+            .serialize_value(&Transcoder::new(deserializer))
+            .map_err(de::Error::custom)
+    }
+}
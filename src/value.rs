@@ -0,0 +1,382 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use rutie::types::ValueType;
+use rutie::{AnyObject, Object, Symbol};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess,
+    IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::{from_object, new_ruby_object, raw, Error, Result};
+
+/// Any Ruby data made of core types, for schemaless arguments and results.
+///
+/// Like `serde_json::Value`, a `Value` can be inspected in Rust and passed through other serde
+/// formats. Hashes keep their order and may have keys of any type. Symbols stay Symbols through
+/// `rutie_serde`; other formats see them as strings, which deserialize into `Value::String`.
+///
+/// Integers must fit in an `i64`: Bignums, like objects of other classes, fail to convert. Use
+/// `RutieObject` to pass arbitrary objects through untouched.
+///
+/// A `Value` is also a `Deserializer`, so Rust types can be deserialized from it without the GVL,
+/// e.g. inside `gvl::without_gvl`; see `from_object_buffered`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Symbol(String),
+    Array(Vec<Value>),
+    Hash(Vec<(Value, Value)>),
+}
+
+impl Value {
+    /// Converts `object`, and everything it contains, into a `Value`.
+    pub fn from_object<O>(object: &O) -> Result<Value>
+    where
+        O: Object,
+    {
+        from_object(object)
+    }
+
+    /// Builds the equivalent Ruby object.
+    pub fn into_ruby(self) -> Result<AnyObject> {
+        new_ruby_object(&self)
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    /// The contents of a String or Symbol.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) | Value::Symbol(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The value of a Hash entry whose key is the String or Symbol `key`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Hash(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key.as_str() == Some(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "a boolean",
+            Value::Integer(_) => "an Integer",
+            Value::Float(_) => "a Float",
+            Value::String(_) => "a String",
+            Value::Symbol(_) => "a Symbol",
+            Value::Array(_) => "an Array",
+            Value::Hash(_) => "a Hash",
+        }
+    }
+}
+
+/// Like `from_object`, but first copies `object`, and everything it contains, into a `Value` in a
+/// single pass, then deserializes `T` from that copy.
+///
+/// The result is a consistent snapshot even if `T`'s `Deserialize` implementation has side
+/// effects, or other threads get to run meanwhile. Only core types can be copied, and
+/// `DeserializerConfig` options don't apply: Symbols read as strings, and Hash keys must match
+/// field names exactly.
+pub fn from_object_buffered<T, O>(object: &O) -> Result<T>
+where
+    T: DeserializeOwned,
+    O: Object,
+{
+    T::deserialize(Value::from_object(object)?)
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::Nil => serializer.serialize_unit(),
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::Integer(value) => serializer.serialize_i64(*value),
+            Value::Float(value) => serializer.serialize_f64(*value),
+            Value::String(value) => serializer.serialize_str(value),
+            Value::Symbol(name) => {
+                raw::serialize_or(&Symbol::new(name).to_any_object(), name, serializer)
+            }
+            Value::Array(elements) => serializer.collect_seq(elements),
+            Value::Hash(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(raw::RAW_OBJECT_TOKEN, ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("nil, a boolean, number, String, Symbol, Array or Hash")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> ::std::result::Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> ::std::result::Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> ::std::result::Result<Value, E>
+    where
+        E: de::Error,
+    {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| E::custom(format!("integer {} is too large for a Value", v)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> ::std::result::Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> ::std::result::Result<Value, E> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> ::std::result::Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_none<E>(self) -> ::std::result::Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> ::std::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    // `rutie_serde`'s `Deserializer` hands over the object itself, so that Symbols can be told
+    // apart from Strings. Other formats see a unit.
+    fn visit_unit<E>(self) -> ::std::result::Result<Value, E>
+    where
+        E: de::Error,
+    {
+        let deserializer = match raw::take_deserializer() {
+            Some(deserializer) => deserializer,
+            None => return Ok(Value::Nil),
+        };
+        let object = deserializer.object();
+        if object.value().ty() == ValueType::Symbol {
+            return Ok(Value::Symbol(Symbol::from(object.value()).to_string()));
+        }
+        deserializer
+            .deserialize_any(ValueVisitor)
+            .map_err(E::custom)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> ::std::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> ::std::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Value::Array(elements))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> ::std::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Value::Hash(entries))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Nil => visitor.visit_unit(),
+            Value::Bool(value) => visitor.visit_bool(value),
+            Value::Integer(value) => visitor.visit_i64(value),
+            Value::Float(value) => visitor.visit_f64(value),
+            Value::String(value) | Value::Symbol(value) => visitor.visit_string(value),
+            Value::Array(elements) => {
+                let mut seq = SeqDeserializer::new(elements.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Hash(entries) => {
+                let mut map = MapDeserializer::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Nil => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Enums are externally tagged, as with `rutie_serde`'s `Deserializer`: unit variants are
+    // Strings or Symbols, and other variants single-entry Hashes.
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::String(variant) | Value::Symbol(variant) => {
+                visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(variant))
+            }
+            Value::Hash(entries) if entries.len() == 1 => {
+                let (tag, value) = entries.into_iter().next().expect("a single entry");
+                match tag {
+                    Value::String(variant) | Value::Symbol(variant) => {
+                        visitor.visit_enum(VariantValue { variant, value })
+                    }
+                    tag => Err(format!(
+                        "Expected a String or Symbol variant of {}, got {}",
+                        name,
+                        tag.describe()
+                    )
+                    .into()),
+                }
+            }
+            value => Err(format!(
+                "Expected a String, Symbol or single-entry Hash for {}, got {}",
+                name,
+                value.describe()
+            )
+            .into()),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+// An enum variant with content, read from `{ variant => value }`.
+struct VariantValue {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for VariantValue {
+    type Error = Error;
+    type Variant = Value;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Value)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant =
+            seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.variant))?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Value {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+}
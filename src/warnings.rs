@@ -0,0 +1,27 @@
+//! A per-thread channel for non-fatal problems noticed during conversion, such as fields that
+//! `Lossy` replaced with their default value.
+//!
+//! Warnings accumulate until they are collected with `take_warnings`, which callers typically do
+//! once per native method call. They are also emitted through the `log` crate.
+
+use std::cell::RefCell;
+
+use log::warn;
+
+thread_local! {
+    static RUTIE_SERDE_WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn push_warning<S>(message: S)
+where
+    S: Into<String>,
+{
+    let message = message.into();
+    warn!("rutie_serde: {}", message);
+    RUTIE_SERDE_WARNINGS.with(|cell| cell.borrow_mut().push(message));
+}
+
+/// Returns and clears the warnings recorded on the current thread.
+pub fn take_warnings() -> Vec<String> {
+    RUTIE_SERDE_WARNINGS.with(|cell| cell.replace(Vec::new()))
+}
@@ -0,0 +1,384 @@
+//! Ready-made `#[serde(with = "...")]` helpers for common field-level tweaks.
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! struct Job {
+//!     #[serde(with = "rutie_serde::with::symbol_string")]
+//!     queue: String,
+//!     #[serde(with = "rutie_serde::with::time_as_epoch")]
+//!     enqueued_at: SystemTime,
+//!     #[serde(with = "rutie_serde::with::ruby_class_name")]
+//!     worker: String,
+//!     #[serde(with = "rutie_serde::with::integer_string")]
+//!     big_id: String,
+//!     #[serde(with = "rutie_serde::with::flat_map")]
+//!     headers: HashMap<String, String>,
+//!     #[serde(with = "rutie_serde::with::handle")]
+//!     callback: AnyObject,
+//!     #[serde(with = "rutie_serde::with::passthrough")]
+//!     options: rutie::Hash,
+//!     #[serde(with = "rutie_serde::with::set")]
+//!     tags: HashSet<String>,
+//! }
+//! ```
+//!
+//! Except where noted, these helpers exchange raw Ruby objects and therefore only work with
+//! `rutie_serde`'s own `Serializer` and `Deserializer`.
+
+use rutie::{AnyObject, Class, Module, Object, RString};
+use serde::{de, ser};
+
+use crate::{raw, Error, Result, ResultExt};
+
+fn to_ser_error<E: ser::Error>(error: Error) -> E {
+    E::custom(error)
+}
+
+fn to_de_error<E: de::Error>(error: Error) -> E {
+    E::custom(error)
+}
+
+fn send_to_string(object: &AnyObject, method: &str) -> Result<String> {
+    Ok(object
+        .protect_send(method, &[])?
+        .try_convert_to::<RString>()?
+        .to_string())
+}
+
+/// A `String` field represented as a Ruby `Symbol`. Deserialization accepts both Symbols and
+/// Strings and works with any serde format.
+pub mod symbol_string {
+    use rutie::Symbol;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S>(value: &str, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        raw::serialize(&Symbol::new(value).to_any_object(), serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)
+    }
+}
+
+/// A `std::time::SystemTime` field represented as a Ruby `Time`. Deserialization accepts
+/// anything responding to `nsec` as a Time, and also Integer or Float seconds since the Unix
+/// epoch (or anything else converting implicitly with `to_f`); Strings and Symbols aren't
+/// parsed.
+pub mod time_as_epoch {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use rutie::{Fixnum, Float, Integer, Symbol};
+    use serde::{Deserializer, Serializer};
+
+    use super::*;
+    use crate::de::integer_to_i64;
+
+    pub fn serialize<S>(value: &SystemTime, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (seconds, nanoseconds) = match value.duration_since(UNIX_EPOCH) {
+            Ok(duration) => (
+                duration.as_secs() as i64,
+                i64::from(duration.subsec_nanos()),
+            ),
+            Err(before_epoch) => {
+                let duration = before_epoch.duration();
+                let mut seconds = -(duration.as_secs() as i64);
+                let mut nanoseconds = i64::from(duration.subsec_nanos());
+                if nanoseconds > 0 {
+                    seconds -= 1;
+                    nanoseconds = 1_000_000_000 - nanoseconds;
+                }
+                (seconds, nanoseconds)
+            }
+        };
+        let time = Class::from_existing("Time")
+            .protect_send(
+                "at",
+                &[
+                    Fixnum::new(seconds).to_any_object(),
+                    Fixnum::new(nanoseconds).to_any_object(),
+                    Symbol::new("nsec").to_any_object(),
+                ],
+            )
+            .map_err(Error::from)
+            .map_err(to_ser_error)?;
+        raw::serialize(&time, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize(deserializer)?;
+        to_system_time(&object)
+            .chain_context(|| "When deserializing a Time as seconds since the epoch")
+            .map_err(to_de_error)
+    }
+
+    fn to_system_time(object: &AnyObject) -> Result<SystemTime> {
+        let (offset, negative) = if object.respond_to("nsec") {
+            let seconds = object
+                .protect_send("to_i", &[])?
+                .try_convert_to::<Integer>()?;
+            let nanoseconds = object
+                .protect_send("nsec", &[])?
+                .try_convert_to::<Integer>()?;
+            let seconds = integer_to_i64(&seconds)?;
+            // `nsec` counts forward from `to_i`, even before the epoch.
+            let offset = Duration::new(seconds.unsigned_abs(), 0);
+            let nanoseconds = Duration::from_nanos(nanoseconds.to_u64());
+            if seconds >= 0 {
+                (offset + nanoseconds, false)
+            } else {
+                (offset.saturating_sub(nanoseconds), true)
+            }
+        } else {
+            let seconds = Float::implicit_to_f(object.clone())?.to_f64();
+            let offset = Duration::try_from_secs_f64(seconds.abs())
+                .map_err(|e| Error::from(format!("{} is not a valid time: {}", seconds, e)))?;
+            (offset, seconds < 0.0)
+        };
+        let time = if negative {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        };
+        time.ok_or_else(|| format!("{:?} from the epoch is out of range", offset).into())
+    }
+}
+
+/// A `String` field holding a Ruby class name. Deserializing any Ruby object yields the name of
+/// its class; serializing looks the named constant up, producing the Class object itself.
+pub mod ruby_class_name {
+    use serde::{Deserializer, Serializer};
+
+    use super::*;
+
+    pub fn serialize<S>(value: &str, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let class = Class::from_existing("Object")
+            .protect_send("const_get", &[RString::new_utf8(value).to_any_object()])
+            .map_err(Error::from)
+            .chain_context(|| format!("When looking up class {}", value))
+            .map_err(to_ser_error)?;
+        raw::serialize(&class, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize(deserializer)?;
+        object
+            .protect_send("class", &[])
+            .map_err(Error::from)
+            .and_then(|class| send_to_string(&class, "name"))
+            .map_err(to_de_error)
+    }
+}
+
+/// A `String` field holding an arbitrarily large integer, represented as a Ruby `Integer`.
+/// Deserialization goes through `Kernel#Integer`, so numeric strings are accepted too.
+pub mod integer_string {
+    use serde::{Deserializer, Serializer};
+
+    use super::*;
+
+    fn kernel_integer(object: AnyObject) -> Result<AnyObject> {
+        Ok(Module::from_existing("Kernel").protect_send("Integer", &[object])?)
+    }
+
+    pub fn serialize<S>(value: &str, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let integer = kernel_integer(RString::new_utf8(value).to_any_object())
+            .chain_context(|| format!("When converting {:?} to an Integer", value))
+            .map_err(to_ser_error)?;
+        raw::serialize(&integer, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize(deserializer)?;
+        kernel_integer(object)
+            .and_then(|integer| send_to_string(&integer, "to_s"))
+            .chain_context(|| "When deserializing an Integer as a String")
+            .map_err(to_de_error)
+    }
+}
+
+/// A map of scalar values with String keys, such as `HashMap<String, String>` or
+/// `BTreeMap<String, i64>`, converted in a single pass by `flat_map_from_object` and
+/// `flat_map_to_object` instead of entry by entry through serde.
+pub mod flat_map {
+    use std::iter::FromIterator;
+
+    use serde::{Deserializer, Serializer};
+
+    use super::*;
+    use crate::{flat_map_from_object, flat_map_to_object, FlatMapValue};
+
+    pub fn serialize<'a, M, K, V, S>(
+        value: &'a M,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+        K: AsRef<str> + 'a,
+        V: FlatMapValue + 'a,
+        S: Serializer,
+    {
+        raw::serialize(&flat_map_to_object(value), serializer)
+    }
+
+    pub fn deserialize<'de, M, V, D>(deserializer: D) -> ::std::result::Result<M, D::Error>
+    where
+        M: FromIterator<(String, V)>,
+        V: FlatMapValue,
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize(deserializer)?;
+        flat_map_from_object(&object).map_err(to_de_error)
+    }
+}
+
+/// An `AnyObject` field exchanged as a handle from the `handles` registry, so it can travel
+/// through any serde format and back within the same process.
+///
+/// Serializing registers the object and writes its handle. Deserializing takes the object back
+/// out of the registry, releasing the handle, so each serialized handle can be deserialized only
+/// once. Works with any serde format.
+pub mod handle {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+    use crate::handles;
+
+    pub fn serialize<S>(value: &AnyObject, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        handles::register(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> ::std::result::Result<AnyObject, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let handle = u64::deserialize(deserializer)?;
+        handles::release(handle).ok_or_else(|| {
+            de::Error::custom(format!(
+                "Handle {} was released or never registered",
+                handle
+            ))
+        })
+    }
+}
+
+/// A `rutie::Object` field, such as an `AnyObject` or a `rutie::Hash`, passed through untouched.
+///
+/// `rutie_serde`'s `Serializer` and `Deserializer` exchange the object itself, as for `Raw<T>`.
+/// Other serde formats, including serde's own buffering for `#[serde(flatten)]` and untagged
+/// enums, see a handle as with `handle`. Deserialization fails if the object isn't of type `O`.
+///
+/// The object stays pinned until the handle is deserialized again (or released with
+/// `handles::release`), so only serialize such fields with other formats if the data comes back.
+pub mod passthrough {
+    use rutie::VerifiedObject;
+    use serde::{Deserializer, Serializer};
+
+    use super::*;
+
+    pub fn serialize<O, S>(value: &O, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        O: Object,
+        S: Serializer,
+    {
+        raw::serialize_or_handle(&value.to_any_object(), serializer)
+    }
+
+    pub fn deserialize<'de, O, D>(deserializer: D) -> ::std::result::Result<O, D::Error>
+    where
+        O: VerifiedObject,
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize_or_handle(deserializer)?;
+        object
+            .try_convert_to::<O>()
+            .map_err(|error| to_de_error(error.into()))
+    }
+}
+
+/// A set type such as `HashSet<T>` or `BTreeSet<T>`, represented as a Ruby `Set` instead of an
+/// Array. Falls back to an Array if the `set` library can't be loaded. Other serde formats see a
+/// sequence. Deserialization accepts Sets, Arrays and other collections.
+pub mod set {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+    use crate::cache;
+
+    pub fn serialize<'a, C, T, S>(
+        value: &'a C,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        &'a C: IntoIterator<Item = &'a T>,
+        T: Serialize + 'a,
+        S: Serializer,
+    {
+        // `rutie_serde`'s `Serializer` serializes the elements with its own configuration and
+        // passes the Array to `new_set`.
+        serializer.serialize_newtype_struct(raw::SET_TOKEN, &Elements(value))
+    }
+
+    struct Elements<'a, C>(&'a C);
+
+    impl<'a, C> Serialize for Elements<'a, C>
+    where
+        &'a C: IntoIterator,
+        <&'a C as IntoIterator>::Item: Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.collect_seq(self.0)
+        }
+    }
+
+    pub fn deserialize<'de, C, D>(deserializer: D) -> ::std::result::Result<C, D::Error>
+    where
+        C: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        C::deserialize(deserializer)
+    }
+
+    /// A `Set` of the elements of `array`, or `array` itself if the `set` library can't be
+    /// loaded.
+    pub(crate) fn new_set(array: AnyObject) -> Result<AnyObject> {
+        if !cache::require("set") {
+            return Ok(array);
+        }
+        Class::from_existing("Set")
+            .protect_send("new", std::slice::from_ref(&array))
+            .map_err(Error::from)
+            .chain_context(|| "When creating a Set")
+    }
+}
@@ -0,0 +1,89 @@
+use std::ops::{Deref, DerefMut};
+
+use rutie::AnyObject;
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::pinned::Pinned;
+use crate::{raw, Error};
+
+/// Deserializes `T` while keeping a pinned handle to the Ruby object it came from, so that
+/// validation layers can point error messages at the exact Ruby value (via `inspect`) that
+/// produced a given field.
+///
+/// Serialization is transparent. Deserialization only works with `rutie_serde`'s `Deserializer`.
+#[derive(Debug, Clone)]
+pub struct WithSource<T> {
+    value: T,
+    source: Pinned,
+}
+
+impl<T> WithSource<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// The Ruby object that `value` was deserialized from.
+    pub fn source(&self) -> &AnyObject {
+        self.source.get()
+    }
+
+    /// The result of calling `inspect` on the source object.
+    pub fn inspect_source(&self) -> String {
+        self.source.inspect()
+    }
+
+    /// Builds an `Error` with `message`, pointing at the source object.
+    pub fn error<S>(&self, message: S) -> Error
+    where
+        S: Into<String>,
+    {
+        let source = self.inspect_source();
+        Error::from(message.into()).chain_context(move || format!("Ruby value: {}", source))
+    }
+}
+
+impl<T> Deref for WithSource<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for WithSource<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<'de, T> Deserialize<'de> for WithSource<T>
+where
+    T: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let inner = raw::deserializer(deserializer)?;
+        let source = Pinned::new(inner.object());
+        let value = T::deserialize(inner).map_err(de::Error::custom)?;
+        Ok(WithSource { value, source })
+    }
+}
+
+impl<T> Serialize for WithSource<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value.serialize(serializer)
+    }
+}
@@ -0,0 +1,224 @@
+use std::any::{self, TypeId};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+
+use rutie::rubysys::typed_data::{rb_check_typeddata, rb_typeddata_is_kind_of};
+use rutie::typed_data::{self, DataTypeWrapper};
+use rutie::types::{c_void, DataType, DataTypeFunction, Value};
+use rutie::{AnyObject, Class, Object, RString};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{raw, Error, Result, ResultExt};
+
+/// Rust types which `Wrapped` can hand to Ruby by reference.
+pub trait RubyWrap: Sized + 'static {
+    /// The constant path of the Ruby class which wrapped values are instances of, e.g.
+    /// `"MyGem::Index"`. It must be defined before values are wrapped.
+    const RUBY_CLASS: &'static str;
+
+    /// Marks the Ruby objects held by the value with `rutie::GC::mark`, so GC keeps them alive
+    /// (and in place) while the value is. It's called during GC, so it must neither allocate Ruby
+    /// objects nor call into Ruby, and mustn't panic.
+    fn mark(&self) {}
+}
+
+/// A Rust value owned by a Ruby object, instead of being serialized into Ruby data.
+///
+/// Big Rust structures which are passed back and forth between Ruby calls, such as indexes or
+/// caches, can be wrapped once and then exchanged by reference: a `Wrapped<T>` serializes to its
+/// Ruby object (an instance of `T::RUBY_CLASS`), and deserializes from it without copying, so it
+/// can be a method's argument or result type, or part of either.
+///
+/// ```ignore
+/// impl RubyWrap for Index {
+///     const RUBY_CLASS: &'static str = "MyGem::Index";
+/// }
+///
+/// fn build(entries: Vec<Entry>) -> Result<Wrapped<Index>, Error> {
+///     Wrapped::new(Index::new(entries))
+/// }
+///
+/// fn lookup(index: Wrapped<Index>, key: String) -> Option<Entry> {
+///     index.get(&key).cloned()
+/// }
+/// ```
+///
+/// The value is dropped when Ruby garbage-collects the object. It can only be borrowed
+/// immutably, as several `Wrapped`s may share it; use interior mutability for state which
+/// changes. Like `RutieObject`, a `Wrapped` doesn't keep its object alive beyond the current
+/// method call unless it's referenced from Ruby.
+pub struct Wrapped<T> {
+    object: AnyObject,
+    marker: PhantomData<T>,
+}
+
+impl<T> Wrapped<T>
+where
+    T: RubyWrap,
+{
+    /// Moves `value` into a new instance of `T::RUBY_CLASS`.
+    pub fn new(value: T) -> Result<Self> {
+        let class = Class::from_existing("Object")
+            .protect_send(
+                "const_get",
+                &[RString::new_utf8(T::RUBY_CLASS).to_any_object()],
+            )
+            .map_err(Error::from)
+            .and_then(|class| Ok(class.try_convert_to::<Class>()?))
+            .chain_context(|| format!("When looking up the class {}", T::RUBY_CLASS))?;
+        let object: AnyObject = class.wrap_data(value, data_type::<T>());
+        Ok(Wrapped {
+            object,
+            marker: PhantomData,
+        })
+    }
+
+    /// The `Wrapped` for a Ruby object created by `Wrapped::<T>::new`, failing if `object` wraps
+    /// anything else.
+    pub fn from_object(object: AnyObject) -> Result<Self> {
+        let data_type = data_type::<T>().data_type();
+        if unsafe { rb_typeddata_is_kind_of(object.value(), data_type) } == 0 {
+            let class_name = object
+                .protect_send("class", &[])
+                .and_then(|class| class.protect_send("name", &[]))
+                .ok()
+                .and_then(|name| name.try_convert_to::<RString>().ok())
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| "an anonymous class".to_owned());
+            return Err(format!(
+                "Expected a wrapped {}, got an instance of {}",
+                any::type_name::<T>(),
+                class_name
+            )
+            .into());
+        }
+        Ok(Wrapped {
+            object,
+            marker: PhantomData,
+        })
+    }
+
+    /// The Ruby object owning the value.
+    pub fn object(&self) -> &AnyObject {
+        &self.object
+    }
+
+    pub fn into_object(self) -> AnyObject {
+        self.object
+    }
+}
+
+impl<T> Deref for Wrapped<T>
+where
+    T: RubyWrap,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The object was checked to wrap a `T` when the `Wrapped` was created, so this doesn't
+        // raise.
+        unsafe {
+            &*(rb_check_typeddata(self.object.value(), data_type::<T>().data_type()) as *const T)
+        }
+    }
+}
+
+impl<T> Clone for Wrapped<T> {
+    fn clone(&self) -> Self {
+        Wrapped {
+            object: self.object.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Wrapped<T>
+where
+    T: RubyWrap + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Wrapped").field(&**self).finish()
+    }
+}
+
+impl<T> Serialize for Wrapped<T> {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        raw::serialize(&self.object, serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Wrapped<T>
+where
+    T: RubyWrap,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object = raw::deserialize(deserializer)?;
+        Wrapped::from_object(object).map_err(de::Error::custom)
+    }
+}
+
+// The Ruby data type of wrapped `T`s, one per `T`.
+struct DataTypeOf<T> {
+    data_type: DataType,
+    marker: PhantomData<fn(T)>,
+}
+
+impl<T> DataTypeWrapper<T> for DataTypeOf<T> {
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+extern "C" fn mark<T>(data: *mut c_void)
+where
+    T: RubyWrap,
+{
+    if let Some(value) = unsafe { (data as *const T).as_ref() } {
+        value.mark();
+    }
+}
+
+static DATA_TYPES: OnceLock<Mutex<HashMap<TypeId, usize>>> = OnceLock::new();
+
+fn data_type<T>() -> &'static DataTypeOf<T>
+where
+    T: RubyWrap,
+{
+    let mut data_types = DATA_TYPES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let address = *data_types.entry(TypeId::of::<T>()).or_insert_with(|| {
+        let name = CString::new(format!("rutie_serde/{}", any::type_name::<T>()))
+            .expect("type names have no NUL bytes");
+        // Ruby keeps pointers to the data type, so it lives forever.
+        let data_type: &'static DataTypeOf<T> = Box::leak(Box::new(DataTypeOf {
+            data_type: DataType {
+                wrap_struct_name: name.into_raw(),
+                parent: ptr::null(),
+                data: ptr::null_mut(),
+                flags: Value::from(0),
+                function: DataTypeFunction {
+                    dmark: Some(mark::<T>),
+                    dfree: Some(typed_data::free::<T>),
+                    dsize: None,
+                    reserved: [ptr::null_mut(); 2],
+                },
+            },
+            marker: PhantomData,
+        }));
+        data_type as *const DataTypeOf<T> as usize
+    });
+    unsafe { &*(address as *const DataTypeOf<T>) }
+}
@@ -0,0 +1,117 @@
+//! Round-trips of enums in each of serde's representations through Ruby objects.
+//!
+//! Requires a Ruby the crate can link against: `cargo test --test enums`.
+
+mod support;
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+enum External {
+    Unit,
+    Newtype(u32),
+    Tuple(i64, String),
+    Struct { name: String, tags: Vec<String> },
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+enum Internal {
+    Unit,
+    Newtype(Point),
+    Struct { name: String, count: u32 },
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "data")]
+enum Adjacent {
+    Unit,
+    Newtype(u32),
+    Tuple(i64, String),
+    Struct { name: String, count: u32 },
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Untagged {
+    Unit,
+    Number(i64),
+    Text(String),
+    Point(Point),
+    List(Vec<Untagged>),
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+fn round_trip<T>(value: T)
+where
+    T: Debug + PartialEq + serde::Serialize + DeserializeOwned,
+{
+    let object = rutie_serde::new_ruby_object(&value)
+        .unwrap_or_else(|e| panic!("Failed to serialize {:?}: {}", value, e));
+    let deserialized: T = rutie_serde::from_object(&object)
+        .unwrap_or_else(|e| panic!("Failed to deserialize {:?}: {}", value, e));
+    assert_eq!(deserialized, value);
+}
+
+fn externally_tagged() {
+    round_trip(External::Unit);
+    round_trip(External::Newtype(7));
+    round_trip(External::Tuple(-3, "three".to_owned()));
+    round_trip(External::Struct {
+        name: "tagged".to_owned(),
+        tags: vec!["a".to_owned(), "b".to_owned()],
+    });
+    round_trip(vec![External::Unit, External::Newtype(1)]);
+
+    let short = rutie_serde::new_ruby_object(HashMap::from([("Tuple", vec![1])])).unwrap();
+    assert!(rutie_serde::from_object::<External, _>(&short).is_err());
+}
+
+fn internally_tagged() {
+    round_trip(Internal::Unit);
+    round_trip(Internal::Newtype(Point { x: 1, y: -1 }));
+    round_trip(Internal::Struct {
+        name: "internal".to_owned(),
+        count: 2,
+    });
+}
+
+fn adjacently_tagged() {
+    round_trip(Adjacent::Unit);
+    round_trip(Adjacent::Newtype(9));
+    round_trip(Adjacent::Tuple(4, "four".to_owned()));
+    round_trip(Adjacent::Struct {
+        name: "adjacent".to_owned(),
+        count: 5,
+    });
+}
+
+fn untagged() {
+    round_trip(Untagged::Unit);
+    round_trip(Untagged::Number(42));
+    round_trip(Untagged::Text("forty-two".to_owned()));
+    round_trip(Untagged::Point(Point { x: 3, y: 4 }));
+    round_trip(Untagged::List(vec![
+        Untagged::Number(1),
+        Untagged::Text("two".to_owned()),
+        Untagged::Unit,
+    ]));
+}
+
+#[test]
+fn enums() {
+    support::vm_init();
+    externally_tagged();
+    internally_tagged();
+    adjacently_tagged();
+    untagged();
+}
@@ -0,0 +1,94 @@
+//! `NonFiniteFloats` options for NaN and infinite floats, when serializing and deserializing.
+//!
+//! Requires a Ruby the crate can link against: `cargo test --test non_finite_floats`.
+
+mod support;
+
+use rutie::{AnyObject, Float, Object};
+use rutie_serde::{DeserializerConfig, NonFiniteFloats, SerializerConfig};
+
+const NON_FINITE: [f64; 3] = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+
+fn serialize(value: f64, non_finite_floats: NonFiniteFloats) -> rutie_serde::Result<AnyObject> {
+    let config = SerializerConfig {
+        non_finite_floats,
+        ..SerializerConfig::default()
+    };
+    rutie_serde::new_ruby_object_with_config(value, config)
+}
+
+fn deserialize<T>(value: f64, non_finite_floats: NonFiniteFloats) -> rutie_serde::Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let config = DeserializerConfig {
+        non_finite_floats,
+        ..DeserializerConfig::default()
+    };
+    rutie_serde::from_object_with_config(&Float::new(value), config)
+}
+
+// Whether two floats are the same, considering NaNs equal.
+fn same(a: f64, b: f64) -> bool {
+    (a.is_nan() && b.is_nan()) || a == b
+}
+
+fn allow() {
+    for &value in NON_FINITE.iter() {
+        let object = serialize(value, NonFiniteFloats::Allow).unwrap();
+        let float = object.try_convert_to::<Float>().unwrap().to_f64();
+        assert!(same(float, value), "serialized {} as {}", value, float);
+
+        let float: f64 = deserialize(value, NonFiniteFloats::Allow).unwrap();
+        assert!(same(float, value), "deserialized {} as {}", value, float);
+        let float: Option<f64> = deserialize(value, NonFiniteFloats::Allow).unwrap();
+        assert!(float.is_some_and(|float| same(float, value)));
+    }
+}
+
+fn error() {
+    for &value in NON_FINITE.iter() {
+        let error = serialize(value, NonFiniteFloats::Error).unwrap_err();
+        assert!(
+            error.to_string().contains("is not a finite number"),
+            "{}",
+            error
+        );
+
+        let error = deserialize::<f64>(value, NonFiniteFloats::Error).unwrap_err();
+        assert!(
+            error.to_string().contains("is not a finite number"),
+            "{}",
+            error
+        );
+        assert!(deserialize::<Option<f64>>(value, NonFiniteFloats::Error).is_err());
+    }
+    // Finite floats are unaffected.
+    assert_eq!(
+        deserialize::<f64>(1.5, NonFiniteFloats::Error).unwrap(),
+        1.5
+    );
+}
+
+fn convert_to_nil() {
+    for &value in NON_FINITE.iter() {
+        let object = serialize(value, NonFiniteFloats::ConvertToNil).unwrap();
+        assert!(object.is_nil(), "serialized {} as {:?}", value, object);
+
+        let float: Option<f64> = deserialize(value, NonFiniteFloats::ConvertToNil).unwrap();
+        assert_eq!(float, None);
+        assert!(deserialize::<f64>(value, NonFiniteFloats::ConvertToNil).is_err());
+    }
+    let float: Option<f64> = deserialize(1.5, NonFiniteFloats::ConvertToNil).unwrap();
+    assert_eq!(float, Some(1.5));
+    let object = serialize(1.5, NonFiniteFloats::ConvertToNil).unwrap();
+    assert_eq!(object.try_convert_to::<Float>().unwrap().to_f64(), 1.5);
+}
+
+#[test]
+fn non_finite_floats() {
+    support::vm_init();
+    allow();
+    error();
+    convert_to_nil();
+}
@@ -0,0 +1,18 @@
+//! Shared setup for the integration tests, which run inside an embedded Ruby VM.
+
+use std::sync::Once;
+
+use rutie::VM;
+
+static VM_INIT: Once = Once::new();
+
+/// Starts the embedded Ruby VM, once per process, with the standard library on the load path.
+///
+/// The VM may only be used from the thread which started it, so each test file runs its cases
+/// from a single `#[test]`.
+pub fn vm_init() {
+    VM_INIT.call_once(|| {
+        VM::init();
+        VM::init_loadpath();
+    });
+}